@@ -0,0 +1,63 @@
+//! Compare the cached tracer of `#[auto_span]` with building the tracer on every call.
+//!
+//! ```sh
+//! cargo bench --bench tracer
+//! ```
+
+use std::{
+    hint::black_box,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use opentelemetry::{
+    global,
+    trace::{TraceContextExt, Tracer, TracerProvider},
+    Context,
+};
+use opentelemetry_auto_span::auto_span;
+use otel_test_helper::{TestTracerProvider, TestTracerProviderInner};
+
+const N: u32 = 1_000_000;
+
+#[auto_span]
+fn cached(x: u64) -> u64 {
+    x + 1
+}
+
+/// The expansion before caching the tracer
+fn uncached(x: u64) -> u64 {
+    let tracer = global::tracer_provider()
+        .tracer_builder(env!("CARGO_PKG_NAME"))
+        .with_version(env!("CARGO_PKG_VERSION"))
+        .build();
+    let span = tracer.start("fn:uncached");
+    let _guard = Context::current_with_span(span).attach();
+    x + 1
+}
+
+fn bench(name: &str, f: fn(u64) -> u64) {
+    let start = Instant::now();
+    for i in 0..N {
+        black_box(f(black_box(i as u64)));
+    }
+    println!("{:<16} {:?}/call", name, start.elapsed() / N);
+}
+
+fn main() {
+    if cfg!(feature = "disabled") {
+        println!("`#[auto_span]` is turned off by the `disabled` feature");
+        return;
+    }
+    // the tracer is cached only once it starts a real span, which the no-op provider never does
+    let mut inner = TestTracerProviderInner::new();
+    inner.sampled = false;
+    let _ = global::set_tracer_provider(TestTracerProvider::new(Arc::new(Mutex::new(inner))));
+
+    // warm up, and build the cached tracer
+    bench("warm up", cached);
+    bench("warm up", uncached);
+
+    bench("global::tracer", uncached);
+    bench("cached tracer", cached);
+}
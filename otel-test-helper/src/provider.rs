@@ -16,6 +16,8 @@ pub struct TestTracerProvider {
 pub struct TestTracerProviderInner {
     pub id: u64,
     pub spans: Vec<(SpanContext, TestSpanData)>,
+    /// If `false`, new spans are not recording and are dropped
+    pub sampled: bool,
 }
 
 impl TestTracerProvider {
@@ -29,6 +31,7 @@ impl TestTracerProviderInner {
         TestTracerProviderInner {
             id: 1,
             spans: Vec::new(),
+            sampled: true,
         }
     }
 
@@ -54,7 +57,7 @@ impl Default for TestTracerProviderInner {
 impl TracerProvider for TestTracerProvider {
     type Tracer = TestTracer;
 
-    fn library_tracer(&self, _library: Arc<InstrumentationLibrary>) -> Self::Tracer {
-        TestTracer::new(self.inner.clone())
+    fn library_tracer(&self, library: Arc<InstrumentationLibrary>) -> Self::Tracer {
+        TestTracer::new(self.inner.clone(), library)
     }
 }
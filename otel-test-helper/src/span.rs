@@ -1,8 +1,8 @@
-use std::{borrow::Cow, collections::HashMap, time::SystemTime};
+use std::{borrow::Cow, collections::HashMap, sync::Arc, time::SystemTime};
 
 use opentelemetry::{
-    trace::{Event, Link, Span, SpanContext, Status},
-    KeyValue,
+    trace::{Event, Link, Span, SpanContext, SpanKind, Status},
+    InstrumentationLibrary, KeyValue,
 };
 
 use crate::tracer::TestTracer;
@@ -17,6 +17,8 @@ pub struct TestSpan {
 #[derive(Debug)]
 pub struct TestSpanData {
     pub name: Cow<'static, str>,
+    pub library: Arc<InstrumentationLibrary>,
+    pub kind: SpanKind,
     pub events: Vec<Event>,
     pub attributes: HashMap<opentelemetry::Key, opentelemetry::Value>,
     pub links: Vec<Link>,
@@ -30,6 +32,8 @@ impl TestSpan {
     {
         let data = TestSpanData {
             name: name.into(),
+            library: tracer.library.clone(),
+            kind: SpanKind::Internal,
             events: Vec::new(),
             attributes: HashMap::new(),
             links: Vec::new(),
@@ -78,7 +82,7 @@ impl Span for TestSpan {
     }
 
     fn is_recording(&self) -> bool {
-        true
+        self.data.is_some()
     }
 
     fn set_attribute(&mut self, attribute: KeyValue) {
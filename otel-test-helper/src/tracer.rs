@@ -5,7 +5,7 @@ use std::{
 
 use opentelemetry::{
     trace::{SpanBuilder, SpanContext, TraceContextExt, Tracer},
-    Context,
+    Context, InstrumentationLibrary,
 };
 
 use crate::{provider::TestTracerProviderInner, span::TestSpan};
@@ -13,14 +13,18 @@ use crate::{provider::TestTracerProviderInner, span::TestSpan};
 #[derive(Debug, Clone)]
 pub struct TestTracer {
     pub provider: Arc<Mutex<TestTracerProviderInner>>,
+    pub library: Arc<InstrumentationLibrary>,
 }
 
 impl TestTracer {
-    pub fn new(provider: Arc<Mutex<TestTracerProviderInner>>) -> TestTracer {
-        TestTracer { provider }
+    pub fn new(
+        provider: Arc<Mutex<TestTracerProviderInner>>,
+        library: Arc<InstrumentationLibrary>,
+    ) -> TestTracer {
+        TestTracer { provider, library }
     }
 
-    pub fn provider(&mut self) -> MutexGuard<TestTracerProviderInner> {
+    pub fn provider(&mut self) -> MutexGuard<'_, TestTracerProviderInner> {
         self.provider.lock().unwrap()
     }
 }
@@ -64,6 +68,20 @@ impl Tracer for TestTracer {
             false,
             Default::default(),
         );
-        TestSpan::new(builder.name, span_context, self.clone())
+        let sampled = provider.sampled;
+        let mut span = TestSpan::new(builder.name, span_context, self.clone());
+        if !sampled {
+            span.data = None;
+        }
+        span.with_data(|data| {
+            if let Some(kind) = builder.span_kind {
+                data.kind = kind;
+            }
+            for kv in builder.attributes.into_iter().flatten() {
+                data.attributes.insert(kv.key, kv.value);
+            }
+            data.links = builder.links.unwrap_or_default();
+        });
+        span
     }
 }
@@ -0,0 +1,235 @@
+use darling::{ast::NestedMeta, util::PathList, Error, FromMeta};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Expr, FnArg, Ident, Lit, Meta, Pat, Path, Signature};
+
+/// `fields(key = expr, ...)`, where `a::b = expr` is recorded as `a.b`
+#[derive(Default)]
+pub struct Fields(Vec<(String, Expr)>);
+
+impl FromMeta for Fields {
+    fn from_list(items: &[NestedMeta]) -> darling::Result<Self> {
+        let mut fields = Vec::new();
+        let mut errors = Error::accumulator();
+        for item in items {
+            match item {
+                NestedMeta::Meta(Meta::NameValue(nv)) => {
+                    fields.push((attribute_key(&nv.path), nv.value.clone()));
+                }
+                _ => errors.push(Error::custom("expected `key = expr`").with_span(item)),
+            }
+        }
+        errors.finish_with(Fields(fields))
+    }
+}
+
+/// `attributes(key = "value", ...)`, which are known at compile time
+#[derive(Default)]
+pub struct Attributes(Vec<(String, Lit)>);
+
+impl FromMeta for Attributes {
+    fn from_list(items: &[NestedMeta]) -> darling::Result<Self> {
+        let mut attributes = Vec::new();
+        let mut errors = Error::accumulator();
+        for item in items {
+            match item {
+                NestedMeta::Meta(Meta::NameValue(nv)) => match &nv.value {
+                    Expr::Lit(lit) => attributes.push((attribute_key(&nv.path), lit.lit.clone())),
+                    value => errors.push(Error::custom("expected a literal").with_span(value)),
+                },
+                _ => errors.push(Error::custom("expected `key = \"value\"`").with_span(item)),
+            }
+        }
+        errors.finish_with(Attributes(attributes))
+    }
+}
+
+/// `a::b` to `a.b`
+fn attribute_key(path: &Path) -> String {
+    path.segments
+        .iter()
+        .map(|s| s.ident.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Build `KeyValue` expressions for the static attributes
+pub fn static_attributes(attributes: &Attributes) -> Vec<TokenStream> {
+    attributes
+        .0
+        .iter()
+        .map(|(key, value)| quote! { ::opentelemetry::KeyValue::new(#key, #value) })
+        .collect()
+}
+
+/// Build `KeyValue` expressions for the function arguments and the user defined fields.
+///
+/// Arguments are recorded as `code.arg.<name>` with their `Display` or `Debug` representation,
+/// or with the type name if the argument implements neither.
+/// The receiver is never recorded.
+pub fn arg_attributes(
+    sig: &Signature,
+    args: bool,
+    skip: Option<&PathList>,
+    fields: &Fields,
+    strict: bool,
+) -> darling::Result<Vec<TokenStream>> {
+    let mut names = Vec::new();
+    for input in &sig.inputs {
+        if let FnArg::Typed(pat) = input {
+            collect_idents(&pat.pat, &mut names);
+        }
+    }
+
+    let mut errors = Error::accumulator();
+    let mut skipped = Vec::new();
+    for path in skip.map(|s| s.iter()).into_iter().flatten() {
+        match path.get_ident() {
+            Some(ident) if names.contains(ident) => skipped.push(ident),
+            _ if !strict => {}
+            _ => errors.push(Error::custom("unknown argument").with_span(path)),
+        }
+    }
+    errors.finish()?;
+
+    let mut attrs = Vec::new();
+    if args || skip.is_some() {
+        for name in names.iter().filter(|n| !skipped.contains(n)) {
+            let key = format!("code.arg.{}", name);
+            let value = field_value(&syn::parse_quote!(#name), true);
+            attrs.push(quote! {
+                ::opentelemetry::KeyValue::new(#key, #value)
+            });
+        }
+    }
+    for (key, expr) in &fields.0 {
+        let value = field_value(expr, false);
+        attrs.push(quote! {
+            ::opentelemetry::KeyValue::new(#key, #value)
+        });
+    }
+    Ok(attrs)
+}
+
+/// Convert the field expression to `Value` by reference, so that the body can still use it.
+///
+/// `Into<Value> + Clone` types are cloned, the others are recorded with `Display` or `Debug`.
+///
+/// For an argument, `Into<Value>` is not tried, because `&str` is `Into<Value>` only if `'static`,
+/// and the types implementing neither `Display` nor `Debug` are recorded with the type name.
+fn field_value(expr: &Expr, arg: bool) -> TokenStream {
+    // autoref specialization: the first impl is on `&&`, the call on `&&&`
+    let display = quote! {
+        trait __OtelAutoDisplay {
+            fn __otel_auto_value(&self) -> ::opentelemetry::Value;
+        }
+    };
+    let debug = quote! {
+        trait __OtelAutoDebug {
+            fn __otel_auto_value(&self) -> ::opentelemetry::Value;
+        }
+    };
+    let impls = if arg {
+        quote! {
+            #display
+            impl<T: ::std::fmt::Display + ?Sized> __OtelAutoDisplay for &&__OtelAutoField<'_, T> {
+                fn __otel_auto_value(&self) -> ::opentelemetry::Value {
+                    ::std::string::ToString::to_string(self.0).into()
+                }
+            }
+            #debug
+            impl<T: ::std::fmt::Debug + ?Sized> __OtelAutoDebug for &__OtelAutoField<'_, T> {
+                fn __otel_auto_value(&self) -> ::opentelemetry::Value {
+                    format!("{:?}", self.0).into()
+                }
+            }
+            trait __OtelAutoTypeName {
+                fn __otel_auto_value(&self) -> ::opentelemetry::Value;
+            }
+            impl<T: ?Sized> __OtelAutoTypeName for __OtelAutoField<'_, T> {
+                fn __otel_auto_value(&self) -> ::opentelemetry::Value {
+                    ::std::any::type_name::<T>().into()
+                }
+            }
+        }
+    } else {
+        quote! {
+            trait __OtelAutoInto {
+                fn __otel_auto_value(&self) -> ::opentelemetry::Value;
+            }
+            impl<T> __OtelAutoInto for &&__OtelAutoField<'_, T>
+            where
+                T: Into<::opentelemetry::Value> + Clone,
+            {
+                fn __otel_auto_value(&self) -> ::opentelemetry::Value {
+                    self.0.clone().into()
+                }
+            }
+            #display
+            impl<T: ::std::fmt::Display + ?Sized> __OtelAutoDisplay for &__OtelAutoField<'_, T> {
+                fn __otel_auto_value(&self) -> ::opentelemetry::Value {
+                    ::std::string::ToString::to_string(self.0).into()
+                }
+            }
+            #debug
+            impl<T: ::std::fmt::Debug + ?Sized> __OtelAutoDebug for __OtelAutoField<'_, T> {
+                fn __otel_auto_value(&self) -> ::opentelemetry::Value {
+                    format!("{:?}", self.0).into()
+                }
+            }
+        }
+    };
+    quote! {
+        {
+            struct __OtelAutoField<'a, T: ?Sized>(&'a T);
+            #impls
+            (&&&__OtelAutoField(&(#expr))).__otel_auto_value()
+        }
+    }
+}
+
+fn collect_idents(pat: &Pat, names: &mut Vec<Ident>) {
+    match pat {
+        Pat::Ident(p) => match &p.subpat {
+            Some((_, sub)) => collect_idents(sub, names),
+            None => names.push(p.ident.clone()),
+        },
+        Pat::Paren(p) => collect_idents(&p.pat, names),
+        Pat::Reference(p) => collect_idents(&p.pat, names),
+        Pat::Slice(p) => p.elems.iter().for_each(|p| collect_idents(p, names)),
+        Pat::Struct(p) => p.fields.iter().for_each(|f| collect_idents(&f.pat, names)),
+        Pat::Tuple(p) => p.elems.iter().for_each(|p| collect_idents(p, names)),
+        Pat::TupleStruct(p) => p.elems.iter().for_each(|p| collect_idents(p, names)),
+        Pat::Type(p) => collect_idents(&p.pat, names),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idents(s: &str) -> Vec<String> {
+        let sig: Signature = syn::parse_str(s).unwrap();
+        let mut names = Vec::new();
+        for input in &sig.inputs {
+            if let FnArg::Typed(pat) = input {
+                collect_idents(&pat.pat, &mut names);
+            }
+        }
+        names.into_iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn test_collect_idents() {
+        assert_eq!(idents("fn f(&self, a: i32, mut b: i32)"), vec!["a", "b"]);
+        assert_eq!(
+            idents("fn f(Path(id): Path<i64>, State(db): State<Pool>)"),
+            vec!["id", "db"],
+        );
+        assert_eq!(
+            idents("fn f((a, _): (i32, i32), P { x, y: z }: P)"),
+            vec!["a", "x", "z"],
+        );
+    }
+}
@@ -1,31 +1,56 @@
 use std::path::{Path, PathBuf};
 
-use syn::{Attribute, File, Item, ItemFn, Meta};
+use syn::{Attribute, File, ImplItem, Item, Meta, TraitItem};
 
-use crate::utils::path_match;
+use crate::utils::{path_match, type_name};
 
-pub fn find_source_path<P: AsRef<Path>>(root: P, func: &ItemFn) -> Option<PathBuf> {
-    let name = func.sig.ident.to_string();
-    walk(root.as_ref(), &mut |file| {
-        is_contain_target_func(file, &name)
-    })
+#[derive(Debug, Eq, PartialEq)]
+pub enum Target {
+    Fn,
+    /// method in `impl` or `trait` block, with the name of the self type or the trait
+    Method(String),
+}
+
+pub fn find_source_path<P: AsRef<Path>>(root: P, name: &str) -> Option<(PathBuf, Target)> {
+    walk(root.as_ref(), &mut |file| find_target_func(file, name))
 }
 
-fn is_contain_target_func(file: File, name: &str) -> bool {
+fn find_target_func(file: File, name: &str) -> Option<Target> {
     for item in file.items {
-        if let Item::Fn(ref func) = item {
-            if func.sig.ident != name {
-                continue;
+        match item {
+            Item::Fn(ref func) if func.sig.ident == name && has_auto_span_attrs(&func.attrs) => {
+                return Some(Target::Fn);
             }
-            if has_auto_span_attrs(&func.attrs) {
-                return true;
+            Item::Impl(ref imp) => {
+                let impl_attr = has_auto_span_attrs(&imp.attrs);
+                for item in &imp.items {
+                    if let ImplItem::Fn(func) = item {
+                        if func.sig.ident == name && (impl_attr || has_auto_span_attrs(&func.attrs))
+                        {
+                            return Some(Target::Method(type_name(&imp.self_ty)));
+                        }
+                    }
+                }
             }
+            Item::Trait(ref tr) => {
+                let trait_attr = has_auto_span_attrs(&tr.attrs);
+                for item in &tr.items {
+                    if let TraitItem::Fn(func) = item {
+                        if func.sig.ident == name
+                            && (trait_attr || has_auto_span_attrs(&func.attrs))
+                        {
+                            return Some(Target::Method(tr.ident.to_string()));
+                        }
+                    }
+                }
+            }
+            _ => {}
         }
     }
-    false
+    None
 }
 
-fn has_auto_span_attrs(attrs: &[Attribute]) -> bool {
+pub fn has_auto_span_attrs(attrs: &[Attribute]) -> bool {
     attrs.iter().any(|attr| match &attr.meta {
         Meta::Path(path) => path_match(path, "auto_span"),
         Meta::List(lis) => path_match(&lis.path, "auto_span"),
@@ -33,9 +58,9 @@ fn has_auto_span_attrs(attrs: &[Attribute]) -> bool {
     })
 }
 
-fn walk<F>(path: &Path, task: &mut F) -> Option<PathBuf>
+fn walk<F, T>(path: &Path, task: &mut F) -> Option<(PathBuf, T)>
 where
-    F: FnMut(File) -> bool,
+    F: FnMut(File) -> Option<T>,
 {
     if path.is_dir() {
         let dir = path.read_dir().ok()?;
@@ -48,8 +73,8 @@ where
     } else if path.extension().and_then(|ex| ex.to_str()) == Some("rs") {
         let content = std::fs::read_to_string(path).ok()?;
         let file = syn::parse_file(&content).ok()?;
-        if task(file) {
-            return Some(path.to_path_buf());
+        if let Some(t) = task(file) {
+            return Some((path.to_path_buf(), t));
         }
     }
     None
@@ -74,10 +99,43 @@ pub fn a() -> &'static str {
     "hello"
 }
 "#;
-        assert!(is_contain_target_func(
-            syn::parse_file(target_file).unwrap(),
-            "a",
-        ));
+        assert_eq!(
+            find_target_func(syn::parse_file(target_file).unwrap(), "a"),
+            Some(Target::Fn),
+        );
+        assert_eq!(
+            find_target_func(syn::parse_file(target_file).unwrap(), "b"),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_find_target_method() {
+        let target_file = r#"
+struct UserRepo;
+
+impl UserRepo {
+    fn plain(&self) {}
+
+    #[auto_span]
+    async fn find(&self) {}
+}
+
+#[auto_span]
+impl<T> Repo<T> {
+    fn all(&self) {}
+}
+
+trait Service {
+    #[auto_span]
+    async fn get(&self) {}
+}
+"#;
+        let find = |name| find_target_func(syn::parse_file(target_file).unwrap(), name);
+        assert_eq!(find("find"), Some(Target::Method("UserRepo".to_owned())));
+        assert_eq!(find("all"), Some(Target::Method("Repo".to_owned())));
+        assert_eq!(find("get"), Some(Target::Method("Service".to_owned())));
+        assert_eq!(find("plain"), None);
     }
 
     #[test]
@@ -88,14 +146,14 @@ pub fn a() -> &'static str {
 pub fn a() -> &'static str {
     "hello"
 }"#;
-        let func_item = syn::parse_str::<ItemFn>(target_func).unwrap();
+        let func_item = syn::parse_str::<syn::ItemFn>(target_func).unwrap();
         assert!(has_auto_span_attrs(&func_item.attrs));
     }
 
     #[test]
     fn strip_attrs_with_option() {
         let target_func = r#"#[auto_span(debug)] pub fn a() -> &'static str { "hello" }"#;
-        let func_item = syn::parse_str::<ItemFn>(target_func).unwrap();
+        let func_item = syn::parse_str::<syn::ItemFn>(target_func).unwrap();
         assert!(has_auto_span_attrs(&func_item.attrs));
     }
 }
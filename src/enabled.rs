@@ -0,0 +1,83 @@
+use darling::{Error, FromMeta};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Block, Expr, ImplItem, Item, TraitItem, TraitItemFn};
+
+use crate::utils::path_match;
+
+/// `enabled = false` or `enabled = cfg!(..)`
+pub enum Enabled {
+    Bool(bool),
+    /// The predicate of `cfg!(..)`, which is left to the compiler
+    Cfg(TokenStream),
+}
+
+impl Default for Enabled {
+    fn default() -> Self {
+        Enabled::Bool(true)
+    }
+}
+
+impl FromMeta for Enabled {
+    fn from_word() -> darling::Result<Self> {
+        Ok(Enabled::Bool(true))
+    }
+
+    fn from_bool(value: bool) -> darling::Result<Self> {
+        Ok(Enabled::Bool(value))
+    }
+
+    fn from_expr(expr: &Expr) -> darling::Result<Self> {
+        match expr {
+            Expr::Macro(m) if path_match(&m.mac.path, "cfg") => {
+                Ok(Enabled::Cfg(m.mac.tokens.clone()))
+            }
+            Expr::Lit(lit) => Self::from_value(&lit.lit),
+            Expr::Group(group) => Self::from_expr(&group.expr),
+            _ => Err(Error::custom("expected `true`, `false` or `cfg!(..)`").with_span(expr)),
+        }
+    }
+}
+
+/// Whether all `#[auto_span]` are turned off by `AUTO_SPAN_DISABLE=1`
+pub fn disabled_by_env() -> bool {
+    std::env::var("AUTO_SPAN_DISABLE").is_ok_and(|v| v == "1" || v == "true")
+}
+
+/// A statement reading `AUTO_SPAN_DISABLE` with `option_env!`, so that cargo rebuilds when it changes
+pub fn track_env_token() -> TokenStream {
+    quote! {
+        let _ = ::core::option_env!("AUTO_SPAN_DISABLE");
+    }
+}
+
+/// Insert [`track_env_token`] into the function bodies of the item, which is otherwise left as is
+pub fn track_env(item: TokenStream) -> TokenStream {
+    let Ok(mut item) = syn::parse2::<Item>(item.clone()) else {
+        return item;
+    };
+    let track = |block: &mut Block| {
+        block
+            .stmts
+            .insert(0, syn::parse2(track_env_token()).unwrap());
+    };
+    match &mut item {
+        Item::Fn(func) => track(&mut func.block),
+        Item::Impl(imp) => imp.items.iter_mut().for_each(|item| {
+            if let ImplItem::Fn(func) = item {
+                track(&mut func.block);
+            }
+        }),
+        Item::Trait(tr) => tr.items.iter_mut().for_each(|item| {
+            if let TraitItem::Fn(TraitItemFn {
+                default: Some(block),
+                ..
+            }) = item
+            {
+                track(block);
+            }
+        }),
+        _ => {}
+    }
+    quote! { #item }
+}
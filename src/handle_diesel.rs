@@ -0,0 +1,97 @@
+use syn::{Expr, ExprLit, ExprMethodCall, Lit};
+
+use crate::utils::path_match;
+
+pub struct DieselQuery {
+    /// The SQL of `diesel::sql_query(..)`, a literal or a constant
+    pub sql: Option<Expr>,
+}
+
+impl DieselQuery {
+    /// The SQL string, if it is written as a literal
+    pub fn sql_str(&self) -> Option<String> {
+        match &self.sql {
+            Some(Expr::Lit(ExprLit {
+                lit: Lit::Str(s), ..
+            })) => Some(s.value()),
+            _ => None,
+        }
+    }
+}
+
+/// Find `query.load(&mut conn)` of diesel and diesel-async.
+///
+/// The argument must be `&mut _`, or a variable known as a connection such as
+/// `conn: &mut PgConnection`, to tell it from such as `AtomicUsize::load`.
+pub fn diesel_query(
+    call: &ExprMethodCall,
+    is_connection: &dyn Fn(&Expr) -> bool,
+) -> Option<DieselQuery> {
+    let query_methods = ["load", "get_result", "get_results", "execute", "first"];
+    if !query_methods.contains(&call.method.to_string().as_str()) || call.args.len() != 1 {
+        return None;
+    }
+    match call.args.first() {
+        Some(Expr::Reference(r)) if r.mutability.is_some() => {}
+        Some(arg @ Expr::Path(path)) if path.path.get_ident().is_some() && is_connection(arg) => {}
+        _ => return None,
+    }
+    Some(DieselQuery {
+        sql: sql_query(&call.receiver),
+    })
+}
+
+/// `diesel::sql_query("...")` at the root of the method chain
+fn sql_query(expr: &Expr) -> Option<Expr> {
+    let mut expr = expr;
+    while let Expr::MethodCall(call) = expr {
+        expr = &call.receiver;
+    }
+    let Expr::Call(call) = expr else {
+        return None;
+    };
+    let Expr::Path(func) = call.func.as_ref() else {
+        return None;
+    };
+    if !path_match(&func.path, vec![vec!["diesel"], vec!["sql_query"]])
+        && !path_match(&func.path, vec![vec!["sql_query"]])
+    {
+        return None;
+    }
+    match call.args.first()? {
+        sql @ (Expr::Lit(_) | Expr::Path(_)) => Some(sql.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sql(s: &str) -> Option<Option<String>> {
+        let call: ExprMethodCall = syn::parse_str(s).unwrap();
+        let is_connection = |arg: &Expr| quote::quote!(#arg).to_string() == "conn";
+        diesel_query(&call, &is_connection).map(|q| q.sql_str())
+    }
+
+    #[test]
+    fn test_diesel_query() {
+        assert_eq!(
+            sql("users.filter(id.eq(1)).load::<User>(&mut conn)"),
+            Some(None)
+        );
+        assert_eq!(
+            sql(
+                r#"diesel::sql_query("SELECT * FROM users").bind::<Integer, _>(1).load(&mut conn)"#
+            ),
+            Some(Some("SELECT * FROM users".to_string())),
+        );
+        // `conn: &mut PgConnection`
+        assert_eq!(
+            sql(r#"sql_query("DELETE FROM users").execute(conn)"#),
+            Some(Some("DELETE FROM users".to_string())),
+        );
+        assert_eq!(sql("users.load(other)"), None);
+        assert_eq!(sql("counter.load(Ordering::SeqCst)"), None);
+    }
+}
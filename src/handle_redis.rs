@@ -0,0 +1,257 @@
+use syn::{Expr, ExprLit, ExprMethodCall, Ident, Lit, UnOp};
+
+use crate::utils::path_match;
+
+/// Find `redis::cmd("GET")...query_async(&mut con)` and returns the command name if known
+pub fn redis_cmd(call: &ExprMethodCall) -> Option<Option<String>> {
+    if !(call.method == "query_async" || call.method == "exec_async") {
+        return None;
+    }
+    let mut expr = call.receiver.as_ref();
+    while let Expr::MethodCall(call) = expr {
+        expr = &call.receiver;
+    }
+    let Expr::Call(root) = expr else {
+        return None;
+    };
+    let Expr::Path(func) = root.func.as_ref() else {
+        return None;
+    };
+    if path_match(&func.path, vec![vec!["redis"], vec!["pipe"]])
+        || path_match(&func.path, vec![vec!["pipe"]])
+    {
+        return Some(None);
+    }
+    if !path_match(&func.path, vec![vec!["redis"], vec!["cmd"]])
+        && !path_match(&func.path, vec![vec!["cmd"]])
+    {
+        return None;
+    }
+    match root.args.first() {
+        Some(Expr::Lit(ExprLit {
+            lit: Lit::Str(s), ..
+        })) => Some(Some(s.value().to_ascii_uppercase())),
+        _ => Some(None),
+    }
+}
+
+/// `AsyncCommands` methods of which the command is not the upper-cased method name
+const COMMANDS: &[(&str, &str)] = &[
+    ("acl_cat", "ACL CAT"),
+    ("acl_cat_categoryname", "ACL CAT"),
+    ("acl_deluser", "ACL DELUSER"),
+    ("acl_dryrun", "ACL DRYRUN"),
+    ("acl_genpass", "ACL GENPASS"),
+    ("acl_genpass_bits", "ACL GENPASS"),
+    ("acl_getuser", "ACL GETUSER"),
+    ("acl_help", "ACL HELP"),
+    ("acl_list", "ACL LIST"),
+    ("acl_load", "ACL LOAD"),
+    ("acl_log", "ACL LOG"),
+    ("acl_log_reset", "ACL LOG"),
+    ("acl_save", "ACL SAVE"),
+    ("acl_setuser", "ACL SETUSER"),
+    ("acl_setuser_rules", "ACL SETUSER"),
+    ("acl_users", "ACL USERS"),
+    ("acl_whoami", "ACL WHOAMI"),
+    ("bit_and", "BITOP AND"),
+    ("bit_not", "BITOP NOT"),
+    ("bit_or", "BITOP OR"),
+    ("bit_xor", "BITOP XOR"),
+    ("bitcount_range", "BITCOUNT"),
+    ("bzmpop_max", "BZMPOP"),
+    ("bzmpop_min", "BZMPOP"),
+    ("client_getname", "CLIENT GETNAME"),
+    ("client_id", "CLIENT ID"),
+    ("client_setname", "CLIENT SETNAME"),
+    ("decr", "DECRBY"),
+    ("expire_at", "EXPIREAT"),
+    ("expire_time", "EXPIRETIME"),
+    ("geo_add", "GEOADD"),
+    ("geo_dist", "GEODIST"),
+    ("geo_hash", "GEOHASH"),
+    ("geo_pos", "GEOPOS"),
+    ("geo_radius", "GEORADIUS"),
+    ("geo_radius_by_member", "GEORADIUSBYMEMBER"),
+    ("get_del", "GETDEL"),
+    ("get_ex", "GETEX"),
+    ("hexpire_at", "HEXPIREAT"),
+    ("hexpire_time", "HEXPIRETIME"),
+    ("hincr", "HINCRBY"),
+    ("hpexpire_at", "HPEXPIREAT"),
+    ("hpexpire_time", "HPEXPIRETIME"),
+    ("hscan_match", "HSCAN"),
+    ("hset_multiple", "HMSET"),
+    ("hset_nx", "HSETNX"),
+    ("incr", "INCRBY"),
+    ("invoke_script", "EVALSHA"),
+    ("key_type", "TYPE"),
+    ("linsert_after", "LINSERT"),
+    ("linsert_before", "LINSERT"),
+    ("lpush_exists", "LPUSHX"),
+    ("mset_nx", "MSETNX"),
+    ("object_encoding", "OBJECT ENCODING"),
+    ("object_freq", "OBJECT FREQ"),
+    ("object_idletime", "OBJECT IDLETIME"),
+    ("object_refcount", "OBJECT REFCOUNT"),
+    ("pexpire_at", "PEXPIREAT"),
+    ("pexpire_time", "PEXPIRETIME"),
+    ("pset_ex", "PSETEX"),
+    ("rename_nx", "RENAMENX"),
+    ("rpush_exists", "RPUSHX"),
+    ("scan_match", "SCAN"),
+    ("set_ex", "SETEX"),
+    ("set_multiple", "MSET"),
+    ("set_nx", "SETNX"),
+    ("set_options", "SET"),
+    ("srandmember_multiple", "SRANDMEMBER"),
+    ("sscan_match", "SSCAN"),
+    ("xadd_map", "XADD"),
+    ("xadd_maxlen", "XADD"),
+    ("xadd_maxlen_map", "XADD"),
+    ("xadd_options", "XADD"),
+    ("xautoclaim_options", "XAUTOCLAIM"),
+    ("xclaim_options", "XCLAIM"),
+    ("xgroup_create", "XGROUP CREATE"),
+    ("xgroup_create_mkstream", "XGROUP CREATE"),
+    ("xgroup_createconsumer", "XGROUP CREATECONSUMER"),
+    ("xgroup_delconsumer", "XGROUP DELCONSUMER"),
+    ("xgroup_destroy", "XGROUP DESTROY"),
+    ("xgroup_setid", "XGROUP SETID"),
+    ("xinfo_consumers", "XINFO CONSUMERS"),
+    ("xinfo_groups", "XINFO GROUPS"),
+    ("xinfo_stream", "XINFO STREAM"),
+    ("xpending_consumer_count", "XPENDING"),
+    ("xpending_count", "XPENDING"),
+    ("xrange_all", "XRANGE"),
+    ("xrange_count", "XRANGE"),
+    ("xread_options", "XREAD"),
+    ("xrevrange_all", "XREVRANGE"),
+    ("xrevrange_count", "XREVRANGE"),
+    ("xtrim_options", "XTRIM"),
+    ("zadd_multiple", "ZADD"),
+    ("zincr", "ZINCRBY"),
+    ("zinterstore_max", "ZINTERSTORE"),
+    ("zinterstore_max_weights", "ZINTERSTORE"),
+    ("zinterstore_min", "ZINTERSTORE"),
+    ("zinterstore_min_weights", "ZINTERSTORE"),
+    ("zinterstore_weights", "ZINTERSTORE"),
+    ("zmpop_max", "ZMPOP"),
+    ("zmpop_min", "ZMPOP"),
+    ("zrandmember_withscores", "ZRANDMEMBER"),
+    ("zrange_withscores", "ZRANGE"),
+    ("zrangebylex_limit", "ZRANGEBYLEX"),
+    ("zrangebyscore_limit", "ZRANGEBYSCORE"),
+    ("zrangebyscore_limit_withscores", "ZRANGEBYSCORE"),
+    ("zrangebyscore_withscores", "ZRANGEBYSCORE"),
+    ("zrembylex", "ZREMRANGEBYLEX"),
+    ("zrembyscore", "ZREMRANGEBYSCORE"),
+    ("zrevrange_withscores", "ZREVRANGE"),
+    ("zrevrangebylex_limit", "ZREVRANGEBYLEX"),
+    ("zrevrangebyscore_limit", "ZREVRANGEBYSCORE"),
+    ("zrevrangebyscore_limit_withscores", "ZREVRANGEBYSCORE"),
+    ("zrevrangebyscore_withscores", "ZREVRANGEBYSCORE"),
+    ("zscan_match", "ZSCAN"),
+    ("zscore_multiple", "ZMSCORE"),
+    ("zunionstore_max", "ZUNIONSTORE"),
+    ("zunionstore_max_weights", "ZUNIONSTORE"),
+    ("zunionstore_min", "ZUNIONSTORE"),
+    ("zunionstore_min_weights", "ZUNIONSTORE"),
+    ("zunionstore_weights", "ZUNIONSTORE"),
+];
+
+/// Methods of the connections which are not commands
+const NOT_COMMANDS: &[&str] = &[
+    "get_db",
+    "req_packed_command",
+    "req_packed_commands",
+    "send_packed_command",
+    "send_packed_commands",
+];
+
+/// Command name of `AsyncCommands` method, such as `SETEX` for `con.set_ex(..)`.
+///
+/// Methods without arguments, such as `pool.get()` of deadpool, and the methods to get
+/// connections are not commands.
+pub fn command_name(call: &ExprMethodCall) -> Option<String> {
+    let method = call.method.to_string();
+    if call.args.is_empty()
+        || method.contains("connection")
+        || method.contains("pubsub")
+        || NOT_COMMANDS.contains(&method.as_str())
+    {
+        return None;
+    }
+    match COMMANDS.iter().find(|(name, _)| *name == method) {
+        Some((_, command)) => Some(command.to_string()),
+        None => Some(method.to_ascii_uppercase()),
+    }
+}
+
+/// The local variable of the receiver, such as `con` of `con.get(..)` or `(&mut con).get(..)`.
+///
+/// Fields such as `self.redis` are not local bindings.
+pub fn local_binding(receiver: &Expr) -> Option<&Ident> {
+    match receiver {
+        Expr::Path(path) if path.qself.is_none() => path.path.get_ident(),
+        Expr::Reference(r) => local_binding(&r.expr),
+        Expr::Paren(p) => local_binding(&p.expr),
+        Expr::Unary(u) if matches!(u.op, UnOp::Deref(_)) => local_binding(&u.expr),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmd(s: &str) -> Option<Option<String>> {
+        let call: ExprMethodCall = syn::parse_str(s).unwrap();
+        redis_cmd(&call)
+    }
+
+    #[test]
+    fn test_redis_cmd() {
+        assert_eq!(
+            cmd(r#"redis::cmd("get").arg(key).query_async::<String>(&mut con)"#),
+            Some(Some("GET".to_string())),
+        );
+        assert_eq!(
+            cmd("redis::pipe().set(k, v).ignore().get(k).query_async(&mut con)"),
+            Some(None),
+        );
+        assert_eq!(cmd(r#"other::cmd("GET").query_async(&mut con)"#), None);
+        assert_eq!(cmd(r#"redis::cmd("GET").arg(key)"#), None);
+
+        let name = |s: &str| command_name(&syn::parse_str(s).unwrap());
+        assert_eq!(
+            name("con.set_ex(key, value, 10)"),
+            Some("SETEX".to_string())
+        );
+        assert_eq!(
+            name("con.hset_multiple(key, &items)"),
+            Some("HMSET".to_string())
+        );
+        assert_eq!(name("con.get(key)"), Some("GET".to_string()));
+        assert_eq!(
+            name("con.xgroup_create(key, g, id)"),
+            Some("XGROUP CREATE".to_string())
+        );
+        assert_eq!(name("pool.get()"), None);
+        assert_eq!(name("client.get_multiplexed_async_connection()"), None);
+        assert_eq!(name("con.send_packed_command(&cmd)"), None);
+    }
+
+    #[test]
+    fn test_local_binding() {
+        let binding = |s: &str| {
+            let expr: Expr = syn::parse_str(s).unwrap();
+            local_binding(&expr).map(|i| i.to_string())
+        };
+        assert_eq!(binding("con"), Some("con".to_string()));
+        assert_eq!(binding("&mut con"), Some("con".to_string()));
+        assert_eq!(binding("(*con)"), Some("con".to_string()));
+        assert_eq!(binding("self.redis"), None);
+        assert_eq!(binding("redis::aio::ConnectionManager"), None);
+    }
+}
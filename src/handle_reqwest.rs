@@ -0,0 +1,118 @@
+use syn::{Expr, ExprLit, Lit, LitStr};
+
+use crate::utils::path_match;
+
+pub struct HttpRequest {
+    /// `GET`, `POST`, ...
+    pub method: String,
+    /// The URL, only if it is a literal
+    pub url: Option<LitStr>,
+}
+
+/// Find `reqwest::get(url)` or `client.get(url)...send()`
+pub fn http_request(expr: &Expr) -> Option<HttpRequest> {
+    match expr {
+        Expr::Call(call) => {
+            let Expr::Path(func) = call.func.as_ref() else {
+                return None;
+            };
+            if !path_match(&func.path, vec![vec!["reqwest"], vec!["get"]]) {
+                return None;
+            }
+            Some(HttpRequest {
+                method: "GET".to_string(),
+                url: call.args.first().and_then(lit_str),
+            })
+        }
+        Expr::MethodCall(call) if call.method == "send" && call.args.is_empty() => {
+            let methods = ["get", "post", "put", "patch", "delete", "head"];
+            let mut expr = call.receiver.as_ref();
+            while let Expr::MethodCall(call) = expr {
+                let method = call.method.to_string();
+                if methods.contains(&method.as_str()) && call.args.len() == 1 {
+                    return Some(HttpRequest {
+                        method: method.to_ascii_uppercase(),
+                        url: call.args.first().and_then(lit_str),
+                    });
+                }
+                // request(Method::GET, url)
+                if method == "request" && call.args.len() == 2 {
+                    let Some(Expr::Path(m)) = call.args.first() else {
+                        return None;
+                    };
+                    return Some(HttpRequest {
+                        method: m.path.segments.last()?.ident.to_string(),
+                        url: call.args.iter().nth(1).and_then(lit_str),
+                    });
+                }
+                expr = &call.receiver;
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+fn lit_str(expr: &Expr) -> Option<LitStr> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(s), ..
+        }) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+/// Add the headers of the global propagator, such as `traceparent`, to the request builder of `.send()`
+pub fn inject_context(expr: &mut Expr) {
+    let Expr::MethodCall(call) = expr else {
+        return;
+    };
+    if call.method != "send" {
+        return;
+    }
+    let builder = &call.receiver;
+    *call.receiver = syn::parse_quote! {
+        {
+            let mut __otel_auto_request = #builder;
+            let mut __otel_auto_headers = ::std::collections::HashMap::<String, String>::new();
+            ::opentelemetry::global::get_text_map_propagator(|propagator| {
+                ::opentelemetry::propagation::TextMapPropagator::inject(
+                    propagator,
+                    &mut __otel_auto_headers,
+                );
+            });
+            for (key, value) in __otel_auto_headers {
+                __otel_auto_request = __otel_auto_request.header(key, value);
+            }
+            __otel_auto_request
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(s: &str) -> Option<(String, Option<String>)> {
+        let expr: Expr = syn::parse_str(s).unwrap();
+        http_request(&expr).map(|r| (r.method, r.url.map(|u| u.value())))
+    }
+
+    #[test]
+    fn test_http_request() {
+        assert_eq!(
+            request(r#"reqwest::get("http://example.com")"#),
+            Some(("GET".to_string(), Some("http://example.com".to_string()))),
+        );
+        assert_eq!(
+            request("client.post(url).json(&body).send()"),
+            Some(("POST".to_string(), None)),
+        );
+        assert_eq!(
+            request(r#"client.request(Method::PUT, "http://example.com").send()"#),
+            Some(("PUT".to_string(), Some("http://example.com".to_string()))),
+        );
+        assert_eq!(request("tx.send(message)"), None);
+        assert_eq!(request("builder.send()"), None);
+    }
+}
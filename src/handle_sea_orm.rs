@@ -0,0 +1,155 @@
+use syn::{visit_mut::VisitMut, Expr, ExprLit, ExprMethodCall, Lit};
+
+use crate::{
+    sql::{statement_token, with_statement, SqlMode},
+    utils::path_match,
+};
+
+pub struct SeaOrmVisitor {
+    mode: SqlMode,
+    system: Option<&'static str>,
+    mutate: bool,
+    sql: Option<String>,
+}
+
+impl SeaOrmVisitor {
+    pub fn new(mode: SqlMode, system: Option<&'static str>) -> SeaOrmVisitor {
+        SeaOrmVisitor {
+            mode,
+            system,
+            mutate: false,
+            sql: None,
+        }
+    }
+
+    pub fn is_mutate(&self) -> bool {
+        self.mutate
+    }
+
+    /// The SQL string, if it is written as a literal
+    pub fn sql(&self) -> Option<&str> {
+        self.sql.as_deref()
+    }
+
+    /// `Entity::find()...one(&db)` or `.all(&db)`
+    fn try_select(&self, call: &ExprMethodCall) -> bool {
+        if !(call.method == "one" || call.method == "all") || call.args.len() != 1 {
+            return false;
+        }
+        let mut expr = call.receiver.as_ref();
+        while let Expr::MethodCall(call) = expr {
+            expr = &call.receiver;
+        }
+        match expr {
+            Expr::Call(call) => match call.func.as_ref() {
+                Expr::Path(path) => {
+                    path.path.segments.len() > 1
+                        && path.path.segments.last().is_some_and(|s| {
+                            s.ident == "find" || s.ident.to_string().starts_with("find_")
+                        })
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// The SQL of `db.execute(Statement::from_string(backend, "..."))`
+    fn try_statement(&self, call: &ExprMethodCall) -> Option<Option<Expr>> {
+        if call.args.len() != 1 {
+            return None;
+        }
+        let arg = call.args.first().unwrap();
+        match call.method.to_string().as_str() {
+            "execute" | "query_one" | "query_all" => {
+                let Expr::Call(stmt) = arg else {
+                    return None;
+                };
+                let Expr::Path(func) = stmt.func.as_ref() else {
+                    return None;
+                };
+                let from_sql = vec!["from_string", "from_sql_and_values"];
+                if !path_match(&func.path, vec![vec!["Statement"], from_sql.clone()])
+                    && !path_match(
+                        &func.path,
+                        vec![vec!["sea_orm"], vec!["Statement"], from_sql],
+                    )
+                {
+                    return None;
+                }
+                Some(stmt.args.iter().nth(1).and_then(sql_expr))
+            }
+            "execute_unprepared" => Some(sql_expr(arg)),
+            _ => None,
+        }
+    }
+}
+
+fn sql_expr(expr: &Expr) -> Option<Expr> {
+    match expr {
+        Expr::Lit(_) | Expr::Path(_) => Some(expr.clone()),
+        _ => None,
+    }
+}
+
+impl VisitMut for SeaOrmVisitor {
+    fn visit_expr_mut(&mut self, i: &mut Expr) {
+        let sql = match i {
+            Expr::MethodCall(call) if self.try_select(call) => {
+                self.mutate = true;
+                return;
+            }
+            Expr::MethodCall(call) => self.try_statement(call),
+            Expr::Await(_) => return,
+            _ => None,
+        };
+        let Some(sql) = sql else {
+            syn::visit_mut::visit_expr_mut(self, i);
+            return;
+        };
+        self.mutate = true;
+        let Some(sql) = sql else {
+            return;
+        };
+        if let Expr::Lit(ExprLit {
+            lit: Lit::Str(s), ..
+        }) = &sql
+        {
+            self.sql = Some(s.value());
+        }
+        if let Some(statement) = statement_token(&sql, self.mode, self.system) {
+            with_statement(i, statement);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visit(s: &str) -> (bool, Option<String>) {
+        let mut expr: Expr = syn::parse_str(s).unwrap();
+        let mut visitor = SeaOrmVisitor::new(SqlMode::Raw, None);
+        visitor.visit_expr_mut(&mut expr);
+        (visitor.is_mutate(), visitor.sql().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_sea_orm() {
+        assert_eq!(
+            visit("user::Entity::find().filter(user::Column::Id.eq(1)).one(&db)"),
+            (true, None),
+        );
+        assert_eq!(visit("Users::find_by_id(1).all(&db)"), (true, None));
+        assert_eq!(
+            visit(r#"db.execute(Statement::from_string(backend, "DELETE FROM users"))"#),
+            (true, Some("DELETE FROM users".to_string())),
+        );
+        assert_eq!(
+            visit(r#"db.execute_unprepared("DELETE FROM users")"#),
+            (true, Some("DELETE FROM users".to_string())),
+        );
+        assert_eq!(visit("futures::future::join(a, b).all(&x)"), (false, None));
+        assert_eq!(visit("items.iter().all(|x| x > 0)"), (false, None));
+    }
+}
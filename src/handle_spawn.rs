@@ -0,0 +1,82 @@
+use proc_macro2::TokenStream;
+use syn::{Expr, ExprCall};
+
+use crate::utils::path_match;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpawnKind {
+    /// `tokio::spawn(future)`
+    Future,
+    /// `tokio::task::spawn_blocking(f)` and `std::thread::spawn(f)`
+    Closure,
+}
+
+/// Find `tokio::spawn(..)`, `tokio::task::spawn_blocking(..)` and `std::thread::spawn(..)`
+pub fn spawn_kind(call: &ExprCall) -> Option<SpawnKind> {
+    if call.args.len() != 1 {
+        return None;
+    }
+    let Expr::Path(func) = call.func.as_ref() else {
+        return None;
+    };
+    let path = &func.path;
+    if path_match(path, vec!["tokio", "spawn"])
+        || path_match(
+            path,
+            vec![vec!["tokio"], vec!["task"], vec!["spawn", "spawn_local"]],
+        )
+    {
+        Some(SpawnKind::Future)
+    } else if path_match(path, vec!["tokio", "task", "spawn_blocking"])
+        || path_match(path, vec!["std", "thread", "spawn"])
+        || path_match(path, vec!["thread", "spawn"])
+    {
+        Some(SpawnKind::Closure)
+    } else {
+        None
+    }
+}
+
+/// Run the spawned future or closure in the context `ctx`, evaluated at the spawn
+pub fn with_context(call: &mut ExprCall, kind: SpawnKind, ctx: TokenStream) {
+    let arg = call.args.first_mut().unwrap();
+    *arg = match kind {
+        SpawnKind::Future => syn::parse_quote! {
+            ::opentelemetry::trace::FutureExt::with_context(#arg, #ctx)
+        },
+        SpawnKind::Closure => syn::parse_quote! {
+            {
+                let __otel_auto_ctx = #ctx;
+                let __otel_auto_fn = #arg;
+                move || {
+                    let __otel_auto_guard = __otel_auto_ctx.attach();
+                    __otel_auto_fn()
+                }
+            }
+        },
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(s: &str) -> Option<SpawnKind> {
+        let call: ExprCall = syn::parse_str(s).unwrap();
+        spawn_kind(&call)
+    }
+
+    #[test]
+    fn test_spawn_kind() {
+        assert_eq!(kind("tokio::spawn(async {})"), Some(SpawnKind::Future));
+        assert_eq!(kind("tokio::task::spawn(fut)"), Some(SpawnKind::Future));
+        assert_eq!(
+            kind("tokio::task::spawn_blocking(move || f(1))"),
+            Some(SpawnKind::Closure)
+        );
+        assert_eq!(kind("std::thread::spawn(f)"), Some(SpawnKind::Closure));
+        assert_eq!(kind("thread::spawn(f)"), Some(SpawnKind::Closure));
+        assert_eq!(kind("spawn(f)"), None);
+        assert_eq!(kind("std::thread::Builder::new(f)"), None);
+    }
+}
@@ -1,54 +1,179 @@
-use quote::quote;
-use syn::{visit_mut::VisitMut, Expr, ExprCall};
+use std::collections::HashSet;
 
-use crate::utils::path_match;
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{
+    punctuated::Punctuated, visit_mut::VisitMut, Expr, ExprCall, ExprLit, ExprMacro,
+    ExprMethodCall, Lit, Path, Token,
+};
 
-pub struct SqlxVisitor {
+use crate::{
+    sql::{statement_token, with_statement, SqlMode},
+    utils::{has_ident, path_match},
+};
+
+pub struct SqlxVisitor<'a> {
+    mode: SqlMode,
+    system: Option<&'static str>,
+    /// Variables known to be `QueryBuilder`
+    query_builders: &'a HashSet<String>,
     mutate: bool,
+    sql: Option<String>,
+    /// The SQL expression to be evaluated once before the query
+    dynamic: Option<TokenStream>,
 }
 
-impl SqlxVisitor {
-    pub fn new() -> SqlxVisitor {
-        SqlxVisitor { mutate: false }
+impl<'a> SqlxVisitor<'a> {
+    pub fn new(
+        mode: SqlMode,
+        system: Option<&'static str>,
+        query_builders: &'a HashSet<String>,
+    ) -> SqlxVisitor<'a> {
+        SqlxVisitor {
+            mode,
+            system,
+            query_builders,
+            mutate: false,
+            sql: None,
+            dynamic: None,
+        }
     }
 
     pub fn is_mutate(&self) -> bool {
         self.mutate
     }
 
-    fn try_sqlx(&self, call: &ExprCall) -> Option<Expr> {
+    /// The SQL string, if it is written as a literal
+    pub fn sql(&self) -> Option<&str> {
+        self.sql.as_deref()
+    }
+
+    /// Evaluate the dynamic SQL expression, record it and then run `expr`.
+    ///
+    /// `match` is used to keep the temporaries in the SQL expression, such as `&format!(..)`,
+    /// alive until the end of the query.
+    pub fn wrap(&self, expr: TokenStream) -> TokenStream {
+        match &self.dynamic {
+            Some(sql) => quote! {
+                match #sql {
+                    __otel_auto_sql => {
+                        ::opentelemetry::trace::get_active_span(|span| {
+                            if span.is_recording() {
+                                span.set_attribute(::opentelemetry::KeyValue::new(
+                                    "db.statement",
+                                    ::std::string::ToString::to_string(&__otel_auto_sql),
+                                ));
+                            }
+                        });
+                        #expr
+                    }
+                }
+            },
+            None => expr,
+        }
+    }
+
+    fn try_sqlx(&mut self, call: &mut ExprCall) -> Option<Expr> {
         if !is_sqlx_query(&call.func) {
             return None;
         }
-        let a = call.args.first()?;
+        let a = call.args.first_mut()?;
         match a {
             Expr::Lit(_) | Expr::Path(_) => Some(a.clone()),
+            _ => {
+                self.mutate = true;
+                if self.mode == SqlMode::Raw {
+                    self.dynamic = Some(quote! { #a });
+                    *a = syn::parse_quote! { __otel_auto_sql };
+                }
+                None
+            }
+        }
+    }
+
+    fn try_sqlx_macro(&self, mac: &ExprMacro) -> Option<Expr> {
+        let pos = sqlx_query_macro_sql_position(&mac.mac.path)?;
+        let args = mac
+            .mac
+            .parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated)
+            .ok()?;
+        match args.into_iter().nth(pos)? {
+            sql @ Expr::Lit(ExprLit {
+                lit: Lit::Str(_), ..
+            }) => Some(sql),
             _ => None,
         }
     }
+
+    /// `builder.build().fetch_all(..)`, where `builder` is a `QueryBuilder`
+    fn try_query_builder(&mut self, call: &ExprMethodCall) -> bool {
+        let execute_methods = [
+            "execute",
+            "execute_many",
+            "fetch",
+            "fetch_all",
+            "fetch_many",
+            "fetch_one",
+            "fetch_optional",
+        ];
+        let build_methods = ["build", "build_query_as", "build_query_scalar"];
+        if !execute_methods.contains(&call.method.to_string().as_str()) {
+            return false;
+        }
+        let Expr::MethodCall(build) = call.receiver.as_ref() else {
+            return false;
+        };
+        if !build_methods.contains(&build.method.to_string().as_str()) {
+            return false;
+        }
+        let builder = &build.receiver;
+        match builder.as_ref() {
+            Expr::Path(path)
+                if path
+                    .path
+                    .get_ident()
+                    .is_some_and(|i| self.query_builders.contains(&i.to_string())) =>
+            {
+                if self.mode == SqlMode::Raw {
+                    self.dynamic = Some(quote! { #builder.sql().to_string() });
+                }
+            }
+            // `QueryBuilder::new(..).build()`, which can not be evaluated twice
+            expr if has_ident(expr.to_token_stream(), "QueryBuilder") => {}
+            _ => return false,
+        }
+        self.mutate = true;
+        true
+    }
 }
 
-impl VisitMut for SqlxVisitor {
+impl VisitMut for SqlxVisitor<'_> {
     fn visit_expr_mut(&mut self, i: &mut Expr) {
         let sql = match i {
             Expr::Call(expr) => self.try_sqlx(expr),
+            Expr::Macro(expr) => self.try_sqlx_macro(expr),
+            Expr::MethodCall(expr) if self.try_query_builder(expr) => return,
             Expr::Await(_) => return,
             _ => None,
         };
-        if let Some(sql) = sql {
-            let t = quote! {
-                {
-                    ::opentelemetry::trace::get_active_span(|span| {
-                        span.set_attribute(::opentelemetry::KeyValue::new("db.statement", #sql));
-                    });
-                    #i
-                }
-            };
-            *i = syn::parse2(t).unwrap();
-            self.mutate = true;
-        } else {
+        if self.mutate && sql.is_none() {
+            return;
+        }
+        let Some(sql) = sql else {
             syn::visit_mut::visit_expr_mut(self, i);
+            return;
+        };
+        self.mutate = true;
+        if let Expr::Lit(ExprLit {
+            lit: Lit::Str(s), ..
+        }) = &sql
+        {
+            self.sql = Some(s.value());
         }
+        let Some(statement) = statement_token(&sql, self.mode, self.system) else {
+            return;
+        };
+        with_statement(i, statement);
     }
 }
 
@@ -67,3 +192,194 @@ fn is_sqlx_query(func: &Expr) -> bool {
         _ => false,
     }
 }
+
+/// Span name of `pool.acquire()`, `pool.begin()`, `tx.commit()` and `tx.rollback()`
+pub fn connection_span_name(call: &ExprMethodCall) -> Option<&'static str> {
+    if !call.args.is_empty() {
+        return None;
+    }
+    match call.method.to_string().as_str() {
+        "acquire" => Some("db.acquire"),
+        "begin" => Some("db.transaction.begin"),
+        "commit" => Some("db.transaction.commit"),
+        "rollback" => Some("db.transaction.rollback"),
+        _ => None,
+    }
+}
+
+/// Max length of the recorded bind parameter
+const MAX_PARAM_LEN: usize = 128;
+
+/// Record the arguments of `.bind(x)` in the method chain as `db.query.parameter.N`.
+pub fn record_binds(expr: &mut Expr) {
+    record_chain_args(expr, "bind");
+}
+
+/// Record the arguments of `.method(x)` in the method chain as `db.query.parameter.N`
+pub fn record_chain_args(expr: &mut Expr, method: &str) {
+    let mut args = Vec::new();
+    let mut expr = expr;
+    while let Expr::MethodCall(call) = expr {
+        if call.method == method && call.args.len() == 1 {
+            args.push(call.args.first_mut().unwrap());
+        }
+        expr = &mut call.receiver;
+    }
+    for (n, arg) in args.into_iter().rev().enumerate() {
+        record_param(arg, n);
+    }
+}
+
+/// Record `arg` as `db.query.parameter.N`.
+///
+/// The value is recorded with `Debug` just before it is moved.
+pub fn record_param(arg: &mut Expr, n: usize) {
+    let key = format!("db.query.parameter.{}", n);
+    *arg = syn::parse_quote! {
+        match #arg {
+            __otel_auto_param => {
+                ::opentelemetry::trace::get_active_span(|span| {
+                    if !span.is_recording() {
+                        return;
+                    }
+                    let mut value = format!("{:?}", &__otel_auto_param);
+                    if let Some((i, _)) = value.char_indices().nth(#MAX_PARAM_LEN) {
+                        value.truncate(i);
+                        value.push_str("...");
+                    }
+                    span.set_attribute(::opentelemetry::KeyValue::new(#key, value));
+                });
+                __otel_auto_param
+            }
+        }
+    };
+}
+
+/// `KeyValue` of the number of rows from `rows`, the `Ok` value of the query
+pub fn rows_token(expr: &Expr) -> Option<TokenStream> {
+    let Expr::MethodCall(call) = expr else {
+        return None;
+    };
+    let (key, value) = match call.method.to_string().as_str() {
+        "fetch_all" => ("db.response.returned_rows", quote! { rows.len() as i64 }),
+        "fetch_optional" => (
+            "db.response.returned_rows",
+            quote! { i64::from(rows.is_some()) },
+        ),
+        "execute" => (
+            "db.response.affected_rows",
+            quote! { rows.rows_affected() as i64 },
+        ),
+        _ => return None,
+    };
+    Some(quote! { ::opentelemetry::KeyValue::new(#key, #value) })
+}
+
+/// Position of the SQL literal in the arguments of `sqlx::query!` family.
+///
+/// The macros are also matched without `sqlx::`, because they are often imported.
+fn sqlx_query_macro_sql_position(path: &Path) -> Option<usize> {
+    let query_macros = vec![
+        "query",
+        "query_unchecked",
+        "query_scalar",
+        "query_scalar_unchecked",
+    ];
+    let query_as_macros = vec!["query_as", "query_as_unchecked"];
+    if path_match(path, vec![vec!["sqlx"], query_macros.clone()])
+        || path_match(path, vec![query_macros])
+    {
+        Some(0)
+    } else if path_match(path, vec![vec!["sqlx"], query_as_macros.clone()])
+        || path_match(path, vec![query_as_macros])
+    {
+        // query_as!(User, "SELECT ...")
+        Some(1)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statement(s: &str) -> Option<String> {
+        let mut expr: Expr = syn::parse_str(s).unwrap();
+        let query_builders = HashSet::new();
+        let mut visitor = SqlxVisitor::new(SqlMode::Raw, None, &query_builders);
+        visitor.visit_expr_mut(&mut expr);
+        if !visitor.is_mutate() {
+            return None;
+        }
+        let re = regex::Regex::new(r#""db.statement" , (".*?")\)"#).unwrap();
+        let tokens = quote! { #expr }.to_string();
+        Some(re.captures(&tokens)?[1].to_string())
+    }
+
+    #[test]
+    fn test_query_macro() {
+        assert_eq!(
+            statement(r#"sqlx::query!("SELECT * FROM users WHERE id = ?", id).fetch_one(&db)"#)
+                .as_deref(),
+            Some(r#""SELECT * FROM users WHERE id = ?""#),
+        );
+        assert_eq!(
+            statement(r#"query_as!(User, "SELECT * FROM users").fetch_all(&db)"#).as_deref(),
+            Some(r#""SELECT * FROM users""#),
+        );
+        assert_eq!(
+            statement(r#"sqlx::query_scalar!("SELECT count(*) FROM users").fetch_one(&db)"#)
+                .as_deref(),
+            Some(r#""SELECT count(*) FROM users""#),
+        );
+        assert_eq!(
+            statement(r#"sqlx::query_file!("query.sql").fetch_one(&db)"#),
+            None
+        );
+        assert_eq!(
+            statement(r#"other::query!("SELECT 1").fetch_one(&db)"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_dynamic_sql() {
+        let query_builders = HashSet::from(["qb".to_string()]);
+        let visit = |mode, s: &str| {
+            let mut expr: Expr = syn::parse_str(s).unwrap();
+            let mut visitor = SqlxVisitor::new(mode, None, &query_builders);
+            visitor.visit_expr_mut(&mut expr);
+            let dynamic = visitor.dynamic.as_ref().map(|t| t.to_string());
+            (visitor.is_mutate(), dynamic, quote! { #expr }.to_string())
+        };
+        assert_eq!(
+            visit(SqlMode::Raw, "sqlx::query(sql.as_str()).execute(&db)"),
+            (
+                true,
+                Some(quote! { sql.as_str() }.to_string()),
+                quote! { sqlx::query(__otel_auto_sql).execute(&db) }.to_string(),
+            ),
+        );
+        assert_eq!(
+            visit(SqlMode::Sanitize, "sqlx::query(sql.as_str()).execute(&db)"),
+            (
+                true,
+                None,
+                quote! { sqlx::query(sql.as_str()).execute(&db) }.to_string(),
+            ),
+        );
+        assert_eq!(
+            visit(SqlMode::Raw, "qb.build().fetch_all(&db)").1,
+            Some(quote! { qb.sql().to_string() }.to_string()),
+        );
+        assert!(
+            visit(
+                SqlMode::Raw,
+                "QueryBuilder::new(\"SELECT 1\").build().fetch_all(&db)"
+            )
+            .0
+        );
+        assert!(!visit(SqlMode::Raw, "other.build().fetch_all(&db)").0);
+    }
+}
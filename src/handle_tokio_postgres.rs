@@ -0,0 +1,122 @@
+use syn::{visit_mut::VisitMut, Expr, ExprLit, ExprMethodCall, Lit};
+
+use crate::sql::{statement_token, with_statement, SqlMode};
+
+pub struct PostgresVisitor<'a> {
+    mode: SqlMode,
+    /// Whether the receiver is known to be a tokio-postgres client
+    is_client: &'a dyn Fn(&Expr) -> bool,
+    mutate: bool,
+    sql: Option<String>,
+}
+
+impl<'a> PostgresVisitor<'a> {
+    pub fn new(mode: SqlMode, is_client: &'a dyn Fn(&Expr) -> bool) -> PostgresVisitor<'a> {
+        PostgresVisitor {
+            mode,
+            is_client,
+            mutate: false,
+            sql: None,
+        }
+    }
+
+    pub fn is_mutate(&self) -> bool {
+        self.mutate
+    }
+
+    /// The SQL string, if it is written as a literal
+    pub fn sql(&self) -> Option<&str> {
+        self.sql.as_deref()
+    }
+
+    /// `client.query(sql, &params)` and so on, returns the first argument.
+    ///
+    /// `execute`, `query`, `query_one` and `query_opt` are common method names,
+    /// so the receiver must be known as a client, or the parameters must be a slice `&[..]`.
+    fn try_query<'e>(&self, call: &'e ExprMethodCall) -> Option<&'e Expr> {
+        let with_params = ["execute", "query", "query_one", "query_opt"];
+        let without_params = ["batch_execute", "simple_query"];
+        let method = call.method.to_string();
+        let mut args = call.args.iter();
+        let sql = args.next()?;
+        if with_params.contains(&method.as_str()) {
+            // `&[&id]`, or `&params` of a known client
+            match (args.next(), args.next()) {
+                (Some(Expr::Reference(params)), None)
+                    if matches!(params.expr.as_ref(), Expr::Array(_))
+                        || (self.is_client)(&call.receiver) =>
+                {
+                    Some(sql)
+                }
+                _ => None,
+            }
+        } else if without_params.contains(&method.as_str()) && args.next().is_none() {
+            Some(sql)
+        } else {
+            None
+        }
+    }
+}
+
+impl VisitMut for PostgresVisitor<'_> {
+    fn visit_expr_mut(&mut self, i: &mut Expr) {
+        let sql = match i {
+            Expr::MethodCall(call) => self.try_query(call).cloned(),
+            Expr::Await(_) => return,
+            _ => None,
+        };
+        let Some(sql) = sql else {
+            syn::visit_mut::visit_expr_mut(self, i);
+            return;
+        };
+        self.mutate = true;
+        // a prepared `&statement` is not recorded
+        if !matches!(sql, Expr::Lit(_) | Expr::Path(_)) {
+            return;
+        }
+        if let Expr::Lit(ExprLit {
+            lit: Lit::Str(s), ..
+        }) = &sql
+        {
+            self.sql = Some(s.value());
+        }
+        if let Some(statement) = statement_token(&sql, self.mode, Some("postgresql")) {
+            with_statement(i, statement);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visit(s: &str) -> (bool, Option<String>) {
+        let mut expr: Expr = syn::parse_str(s).unwrap();
+        let is_client = |receiver: &Expr| quote::quote!(#receiver).to_string() == "client";
+        let mut visitor = PostgresVisitor::new(SqlMode::Raw, &is_client);
+        visitor.visit_expr_mut(&mut expr);
+        (visitor.is_mutate(), visitor.sql().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_tokio_postgres() {
+        assert_eq!(
+            visit(r#"client.query("SELECT * FROM users WHERE id = $1", &[&id])"#),
+            (true, Some("SELECT * FROM users WHERE id = $1".to_string())),
+        );
+        assert_eq!(visit("client.execute(&stmt, &params)"), (true, None));
+        assert_eq!(
+            visit(r#"client.batch_execute("DROP TABLE users")"#),
+            (true, Some("DROP TABLE users".to_string())),
+        );
+        assert_eq!(visit("client.execute(request)"), (false, None));
+        assert_eq!(visit("reqwest::Client::new().query(&q)"), (false, None));
+        // not known as a client, and the parameters are not a slice
+        assert_eq!(visit("cache.execute(key, &value)"), (false, None));
+        assert_eq!(visit("db.query(q, &opts)"), (false, None));
+        assert_eq!(
+            visit(r#"db.query_one("SELECT 1", &[])"#),
+            (true, Some("SELECT 1".to_string())),
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use darling::{Error, FromMeta};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// `kind = "server"`, the `SpanKind` of the function span
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    #[default]
+    Internal,
+    Server,
+    Client,
+    Producer,
+    Consumer,
+}
+
+impl SpanKind {
+    pub fn token(self) -> TokenStream {
+        match self {
+            SpanKind::Internal => quote! { ::opentelemetry::trace::SpanKind::Internal },
+            SpanKind::Server => quote! { ::opentelemetry::trace::SpanKind::Server },
+            SpanKind::Client => quote! { ::opentelemetry::trace::SpanKind::Client },
+            SpanKind::Producer => quote! { ::opentelemetry::trace::SpanKind::Producer },
+            SpanKind::Consumer => quote! { ::opentelemetry::trace::SpanKind::Consumer },
+        }
+    }
+}
+
+impl FromMeta for SpanKind {
+    fn from_string(value: &str) -> darling::Result<Self> {
+        match value {
+            "internal" => Ok(SpanKind::Internal),
+            "server" => Ok(SpanKind::Server),
+            "client" => Ok(SpanKind::Client),
+            "producer" => Ok(SpanKind::Producer),
+            "consumer" => Ok(SpanKind::Consumer),
+            _ => Err(Error::unknown_value(value)),
+        }
+    }
+}
@@ -1,23 +1,66 @@
+mod args;
 mod dig;
+mod enabled;
+#[cfg(feature = "diesel")]
+mod handle_diesel;
+#[cfg(feature = "redis")]
+mod handle_redis;
+#[cfg(feature = "reqwest")]
+mod handle_reqwest;
+#[cfg(feature = "sea-orm")]
+mod handle_sea_orm;
+mod handle_spawn;
 mod handle_sqlx;
+#[cfg(feature = "tokio-postgres")]
+mod handle_tokio_postgres;
+mod kind;
 mod line;
+mod name;
+mod ret;
+mod sql;
 mod utils;
 
+use std::collections::{HashMap, HashSet};
+
 use darling::ast::NestedMeta;
-use darling::{Error, FromMeta};
-use proc_macro2::{Ident, Span, TokenStream};
-use quote::{quote, quote_spanned};
+use darling::{util::PathList, Error, FromMeta};
+use proc_macro2::{Ident, Span, TokenStream, TokenTree};
+use quote::{quote, quote_spanned, ToTokens};
 use syn::{
-    parse_macro_input, spanned::Spanned, visit_mut::VisitMut, Expr, ExprAwait, ExprClosure,
-    ExprTry, ItemFn, Signature,
+    parse_macro_input, spanned::Spanned, visit_mut::VisitMut, Attribute, Block, Expr, ExprAsync,
+    ExprAwait, ExprCall, ExprClosure, ExprTry, ExprTryBlock, FnArg, ImplItem, Item, Local, Pat,
+    ReturnType, Signature, Stmt, TraitItem,
 };
 
-use crate::{dig::find_source_path, line::LineAccess};
+use crate::{
+    dig::{find_source_path, has_auto_span_attrs, Target},
+    enabled::Enabled,
+    line::LineAccess,
+    ret::{future_output, ReturnTypeContext, TypeAliases},
+    utils::{has_ident, path_match, type_name},
+};
 
 #[derive(Default, FromMeta)]
 #[darling(default)]
 struct Opt {
     pub debug: bool,
+    pub name: Option<String>,
+    pub args: bool,
+    pub skip: Option<PathList>,
+    pub fields: args::Fields,
+    pub ret: bool,
+    pub exception_events: bool,
+    pub result: bool,
+    pub no_result: bool,
+    pub sql: Option<sql::SqlMode>,
+    pub db_params: bool,
+    pub db_rows: bool,
+    pub propagate: bool,
+    pub tracer: Option<String>,
+    pub enabled: enabled::Enabled,
+    pub kind: kind::SpanKind,
+    pub attributes: args::Attributes,
+    pub spawn_span: bool,
 }
 
 #[proc_macro_attribute]
@@ -38,16 +81,95 @@ pub fn auto_span(
         }
     };
 
-    let mut input = parse_macro_input!(item as ItemFn);
+    // return the input as is, without instrumentation
+    if cfg!(feature = "disabled") || matches!(opt.enabled, Enabled::Bool(false)) {
+        return item;
+    }
+    // the variable is read in the function bodies, so that unsetting it instruments them again
+    if enabled::disabled_by_env() {
+        return enabled::track_env(item.into()).into();
+    }
+    let original = match opt.enabled {
+        Enabled::Cfg(_) => enabled::track_env(item.clone().into()),
+        Enabled::Bool(_) => TokenStream::new(),
+    };
 
-    let mut dir = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
-    dir.push("src");
-    let line_access = find_source_path(dir, &input).map(LineAccess::new);
-    let mut visitor = AutoSpanVisitor::new(line_access);
-    visitor.visit_item_fn_mut(&mut input);
+    let input = parse_macro_input!(item as Item);
+    let (token, debug_name) = match input {
+        Item::Fn(mut func) => {
+            let name = func.sig.ident.to_string();
+            let found = invocation_file()
+                .and_then(|path| find_source_path(path, &name))
+                .or_else(|| find_source_path(source_dir(), &name));
+            let (path, target) = match found {
+                Some((path, target)) => (Some(path), target),
+                None => (invocation_file(), Target::Fn),
+            };
+            let self_ty = match target {
+                Target::Fn => None,
+                Target::Method(self_ty) => Some(self_ty),
+            };
+            let aliases = TypeAliases::from_path(path.as_deref());
+            let line_access = path.map(LineAccess::new);
+            let result = expand_fn(
+                &opt,
+                &func.sig,
+                &mut func.block,
+                self_ty.as_deref(),
+                line_access,
+                &aliases,
+                true,
+            );
+            if let Err(e) = result {
+                return proc_macro::TokenStream::from(e.write_errors());
+            }
+            (quote! {#func}, name)
+        }
+        Item::Impl(mut imp) => {
+            let self_ty = type_name(&imp.self_ty);
+            let methods = imp.items.iter_mut().filter_map(|item| match item {
+                ImplItem::Fn(func) => Some((&func.attrs[..], &func.sig, &mut func.block)),
+                _ => None,
+            });
+            if let Err(e) = expand_methods(&opt, &self_ty, methods) {
+                return proc_macro::TokenStream::from(e.write_errors());
+            }
+            (quote! {#imp}, self_ty)
+        }
+        Item::Trait(mut tr) => {
+            let self_ty = tr.ident.to_string();
+            // only default methods have a body to instrument
+            let methods = tr.items.iter_mut().filter_map(|item| match item {
+                TraitItem::Fn(func) => func
+                    .default
+                    .as_mut()
+                    .map(|block| (&func.attrs[..], &func.sig, block)),
+                _ => None,
+            });
+            if let Err(e) = expand_methods(&opt, &self_ty, methods) {
+                return proc_macro::TokenStream::from(e.write_errors());
+            }
+            (quote! {#tr}, self_ty)
+        }
+        item => {
+            return proc_macro::TokenStream::from(
+                Error::custom("`auto_span` can only be applied to `fn`, `impl` or `trait`")
+                    .with_span(&item)
+                    .write_errors(),
+            );
+        }
+    };
 
-    insert_function_span(&mut input);
-    let token = quote! {#input};
+    // keep the original item for `not(..)` of `enabled = cfg!(..)`
+    let token = match &opt.enabled {
+        Enabled::Cfg(predicate) => quote! {
+            #[cfg(#predicate)]
+            #token
+            #[cfg(not(#predicate))]
+            #original
+        },
+        Enabled::Bool(_) => token,
+    };
 
     if opt.debug {
         let mut target = std::path::PathBuf::from(
@@ -55,53 +177,539 @@ pub fn auto_span(
         );
         target.push("auto-span");
         std::fs::create_dir_all(&target).unwrap();
-        target.push(format!("{}.rs", input.sig.ident));
+        target.push(format!("{}.rs", debug_name));
         std::fs::write(&target, format!("{}", token)).unwrap();
     }
 
     token.into()
 }
 
-fn insert_function_span(i: &mut ItemFn) {
+/// The file of the `#[auto_span]` attribute, if it is a local file.
+///
+/// Otherwise the file is searched under `src/` by the function name.
+#[rustversion::since(1.88)]
+fn invocation_file() -> Option<std::path::PathBuf> {
+    proc_macro::Span::call_site().local_file()
+}
+
+/// `Span::local_file()` is stable since Rust 1.88, so the file is only searched under `src/`.
+#[rustversion::before(1.88)]
+fn invocation_file() -> Option<std::path::PathBuf> {
+    None
+}
+
+fn source_dir() -> std::path::PathBuf {
+    let mut dir = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    dir.push("src");
+    dir
+}
+
+/// Instrument the methods of `impl` or `trait` block.
+fn expand_methods<'a, I>(opt: &Opt, self_ty: &str, methods: I) -> darling::Result<()>
+where
+    I: Iterator<Item = (&'a [Attribute], &'a Signature, &'a mut Block)>,
+{
+    let mut path = None;
+    let mut aliases = None;
+    let mut errors = Error::accumulator();
+    for (attrs, sig, block) in methods {
+        // methods with its own `#[auto_span]` are expanded by themselves
+        if has_auto_span_attrs(attrs) {
+            continue;
+        }
+        // a span can not be started in `const fn`
+        if sig.constness.is_some() {
+            continue;
+        }
+        if path.is_none() {
+            path = invocation_file().or_else(|| {
+                find_source_path(source_dir(), &sig.ident.to_string()).map(|(path, _)| path)
+            });
+        }
+        let aliases = aliases.get_or_insert_with(|| TypeAliases::from_path(path.as_deref()));
+        let line_access = path.as_ref().map(LineAccess::new);
+        errors.handle(expand_fn(
+            opt,
+            sig,
+            block,
+            Some(self_ty),
+            line_access,
+            aliases,
+            false,
+        ));
+    }
+    errors.finish()
+}
+
+/// Instrument a function body.
+///
+/// `strict` rejects `skip` of arguments which do not exist,
+/// it is relaxed for `impl` level attribute because `skip` is shared by all methods.
+fn expand_fn(
+    opt: &Opt,
+    sig: &Signature,
+    block: &mut Block,
+    self_ty: Option<&str>,
+    line_access: Option<LineAccess>,
+    aliases: &TypeAliases,
+    strict: bool,
+) -> darling::Result<()> {
+    let mut attributes = args::static_attributes(&opt.attributes);
+    attributes.extend(args::arg_attributes(
+        sig,
+        opt.args,
+        opt.skip.as_ref(),
+        &opt.fields,
+        strict,
+    )?);
+
+    let output = match &sig.output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => Some(ty.as_ref()),
+    };
+    // type of the value produced by the body
+    let (kind, value_ty) = match output.and_then(future_output) {
+        _ if sig.asyncness.is_some() => (FnKind::Async, output),
+        Some(ty) if future_body(block).is_some() => (FnKind::Future, Some(ty)),
+        _ => (FnKind::Sync, output),
+    };
+    // `rt` is for the returned value, `try_rt` is for `?` in the body
+    let (rt, try_rt) = match (opt.result, opt.no_result) {
+        (true, true) => {
+            return Err(Error::custom("`result` and `no_result` are exclusive"));
+        }
+        (true, false) => (ReturnTypeContext::Result, ReturnTypeContext::Result),
+        (false, true) => (ReturnTypeContext::Unknown, ReturnTypeContext::Unknown),
+        (false, false) => match value_ty {
+            Some(ty) => (aliases.type_context(ty), aliases.try_context(ty)),
+            None => (ReturnTypeContext::Unknown, ReturnTypeContext::Unknown),
+        },
+    };
+    let ret_ty = ret::annotation_type(value_ty);
+
+    let mut visitor = AutoSpanVisitor::new(line_access, aliases, opt);
+    visitor.record_variables(sig);
+    // the error status is set by the returned value, `?` of the body only adds the line
+    visitor.status_at_exit = rt == ReturnTypeContext::Result;
+    match kind {
+        FnKind::Future => {
+            visitor.visit_fn_body(try_rt, &mut future_body(block).unwrap().0.block);
+        }
+        _ => visitor.visit_fn_body(try_rt, block),
+    }
+
+    let fn_name = match self_ty {
+        Some(self_ty) => format!("{}::{}", self_ty, sig.ident),
+        None => sig.ident.to_string(),
+    };
+    let mut inspect = TokenStream::new();
+    if opt.ret {
+        inspect.extend(record_return_token(rt));
+    }
+    if let ReturnTypeContext::Result = rt {
+        inspect.extend(error_status_token(opt.exception_events));
+    }
+    if !inspect.is_empty() {
+        let stmts = match kind {
+            FnKind::Future => &mut future_body(block).unwrap().0.block.stmts,
+            _ => &mut block.stmts,
+        };
+        let is_async = !matches!(kind, FnKind::Sync);
+        ret::inspect_return(stmts, is_async, ret_ty.as_ref(), &inspect);
+    }
+    insert_function_span(kind, block, &fn_name, opt, &attributes);
+    Ok(())
+}
+
+#[derive(Copy, Clone)]
+enum FnKind {
+    Sync,
+    Async,
+    /// returns a future built by `async` block, such as the method desugared by `#[async_trait]`
+    Future,
+}
+
+/// Find the tail `async { .. }` or `Box::pin(async { .. })` of the body.
+///
+/// The second value is `true` when the block is pinned.
+fn future_body(block: &mut Block) -> Option<(&mut ExprAsync, bool)> {
+    let Some(Stmt::Expr(expr, None)) = block.stmts.last_mut() else {
+        return None;
+    };
+    match expr {
+        Expr::Async(body) => Some((body, false)),
+        Expr::Call(call) => {
+            let Expr::Path(func) = call.func.as_ref() else {
+                return None;
+            };
+            if !path_match(&func.path, vec!["Box", "pin"]) || call.args.len() != 1 {
+                return None;
+            }
+            match call.args.first_mut() {
+                Some(Expr::Async(body)) => Some((body, true)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn insert_function_span(
+    kind: FnKind,
+    block: &mut Block,
+    fn_name: &str,
+    opt: &Opt,
+    attributes: &[TokenStream],
+) {
+    // the instrumentation scope is the crate of the function, unless `tracer` is given
+    let scope = match &opt.tracer {
+        Some(tracer) => quote! { #tracer },
+        None => quote! { ::core::env!("CARGO_PKG_NAME") },
+    };
+    // The tracer is cached per function, once it starts a real span.
+    // Until then, such as before the global provider is set, it is taken from the global provider
+    // on every call.
+    // The items are visible from the closures and `async` blocks of the body without capturing.
     let def_tracer = quote! {
-        let __otel_auto_tracer = ::opentelemetry::global::tracer("");
+        static __OTEL_AUTO_TRACER: ::std::sync::OnceLock<::opentelemetry::global::BoxedTracer> =
+            ::std::sync::OnceLock::new();
+        fn __otel_auto_with_tracer<S, F>(f: F) -> S
+        where
+            S: ::opentelemetry::trace::Span,
+            F: FnOnce(&::opentelemetry::global::BoxedTracer) -> S,
+        {
+            if let Some(tracer) = __OTEL_AUTO_TRACER.get() {
+                return f(tracer);
+            }
+            let tracer = ::opentelemetry::trace::TracerProvider::tracer_builder(
+                &::opentelemetry::global::tracer_provider(),
+                #scope,
+            )
+            .with_version(::core::env!("CARGO_PKG_VERSION"))
+            .build();
+            let span = f(&tracer);
+            // a no-op tracer returns an invalid context, or the context of the parent
+            let span_context = ::opentelemetry::trace::Span::span_context(&span);
+            let parent = ::opentelemetry::trace::TraceContextExt::span(
+                &::opentelemetry::Context::current(),
+            )
+            .span_context()
+            .span_id();
+            if span_context.is_valid() && span_context.span_id() != parent {
+                let _ = __OTEL_AUTO_TRACER.set(tracer);
+            }
+            span
+        }
+    };
+    // End the function span when the body finishes, even if a spawned task or thread still
+    // holds a clone of the context.
+    // The spans started in such a task remain children of the ended span.
+    let def_end = quote! {
+        struct __OtelAutoEnd(::opentelemetry::Context);
+        impl Drop for __OtelAutoEnd {
+            fn drop(&mut self) {
+                ::opentelemetry::trace::TraceContextExt::span(&self.0).end();
+            }
+        }
     };
+    let end = quote! {
+        let __otel_auto_end = __OtelAutoEnd(::opentelemetry::Context::current());
+    };
+    let track_env = enabled::track_env_token();
     let span_ident = Ident::new("span", Span::call_site());
-    let start_tracer = otel_start_tracer_token(&format!("fn:{}", i.sig.ident));
+    let template = opt.name.as_deref().unwrap_or(name::DEFAULT_TEMPLATE);
+    let span_name = name::span_name(template, fn_name);
+    // evaluate attributes before the body can move the arguments
+    let def_attributes = otel_attributes_token(attributes);
+    let start_span = otel_start_span_token(&span_ident, &span_name, opt.kind, attributes);
     let ctx = otel_ctx_token(&span_ident);
-    let stmts = &i.block.stmts;
-    let tokens = if i.sig.asyncness.is_some() {
-        quote! {
+    let future = match kind {
+        FnKind::Future => future_body(block).map(|(body, boxed)| {
+            let mut body = body.clone();
+            body.block
+                .stmts
+                .insert(0, syn::parse2(end.clone()).unwrap());
+            (body, boxed)
+        }),
+        _ => None,
+    };
+    let stmts = &block.stmts;
+    let tokens = match kind {
+        FnKind::Sync => quote! {
             #def_tracer
+            #def_end
+            #track_env
+            #def_attributes
+            #start_span
+            let __otel_auto_ctx = #ctx;
+            let __otel_auto_guard = __otel_auto_ctx.clone().attach();
+            #end
+            #(#stmts)*
+        },
+        FnKind::Async => quote! {
+            #def_tracer
+            #def_end
+            #track_env
+            #def_attributes
             ::opentelemetry::trace::FutureExt::with_context(
-                async {#(#stmts)*},
+                async {
+                    #end
+                    #(#stmts)*
+                },
                 {
-                    let #span_ident = #start_tracer;
+                    #start_span
                     #ctx
                 }
             ).await
-        }
-    } else {
-        quote! {
-            #def_tracer
-            let #span_ident = #start_tracer;
-            let __otel_auto_ctx = #ctx;
-            let __otel_auto_guard = __otel_auto_ctx.clone().attach();
-            #(#stmts)*
+        },
+        FnKind::Future => {
+            // keep the statements before the future, and wrap the future
+            let leading = &stmts[..stmts.len() - 1];
+            let (body, boxed) = future.unwrap();
+            let future = quote! {
+                ::opentelemetry::trace::FutureExt::with_context(
+                    #body,
+                    {
+                        #start_span
+                        #ctx
+                    }
+                )
+            };
+            let future = if boxed {
+                quote! { Box::pin(#future) }
+            } else {
+                future
+            };
+            quote! {
+                #def_tracer
+                #def_end
+                #track_env
+                #def_attributes
+                #(#leading)*
+                #future
+            }
         }
     };
     let body: Expr = syn::parse2(quote! {{#tokens}}).unwrap();
     match body {
-        Expr::Block(block) => {
-            i.block.stmts = block.block.stmts;
+        Expr::Block(body) => {
+            block.stmts = body.block.stmts;
         }
         _ => unreachable!(),
     }
 }
 
-fn otel_start_tracer_token(name: &str) -> TokenStream {
+/// Record the returned value as `code.return` on the current span.
+///
+/// `Result` and `Option` only record the variant name, other types are recorded with `Debug`.
+fn record_return_token(rt: ReturnTypeContext) -> TokenStream {
+    let ret = ret::ret_ident();
+    let value = match rt {
+        ReturnTypeContext::Result => quote! {
+            match &#ret { Ok(_) => "Ok", Err(_) => "Err" }
+        },
+        ReturnTypeContext::Option => quote! {
+            match &#ret { Some(_) => "Some", None => "None" }
+        },
+        ReturnTypeContext::Unknown => quote! {
+            format!("{:?}", #ret)
+        },
+    };
     quote! {
-        ::opentelemetry::trace::Tracer::start(&__otel_auto_tracer, #name)
+        ::opentelemetry::trace::get_active_span(|__otel_auto_span| {
+            if __otel_auto_span.is_recording() {
+                __otel_auto_span.set_attribute(::opentelemetry::KeyValue::new("code.return", #value));
+            }
+        });
+    }
+}
+
+/// Set error status when the returned `Result` is `Err`.
+fn error_status_token(exception_events: bool) -> TokenStream {
+    let ret = ret::ret_ident();
+    let span_ident = Ident::new("__otel_auto_span", Span::call_site());
+    let event = if exception_events {
+        exception_event_token(&span_ident, &[])
+    } else {
+        TokenStream::new()
+    };
+    let message = error_message_token();
+    let tokens = if_recording(
+        &span_ident,
+        quote! {
+            #span_ident.set_status(::opentelemetry::trace::Status::error(#message));
+            #event
+        },
+    );
+    quote! {
+        if let Err(e) = &#ret {
+            ::opentelemetry::trace::get_active_span(|#span_ident| {
+                #tokens
+            });
+        }
+    }
+}
+
+/// The message of the error `e`, by `Display` or else `Debug`, or empty if `e` implements neither.
+///
+/// The implementation is chosen by autoref specialization, so any error type is accepted.
+fn error_message_token() -> TokenStream {
+    quote! {
+        {
+            struct __OtelAutoError<'a, T: ?Sized>(&'a T);
+            trait __OtelAutoDisplay {
+                fn __otel_auto_message(&self) -> String;
+            }
+            impl<T: ::std::fmt::Display + ?Sized> __OtelAutoDisplay for &&__OtelAutoError<'_, T> {
+                fn __otel_auto_message(&self) -> String {
+                    ::std::string::ToString::to_string(self.0)
+                }
+            }
+            trait __OtelAutoDebug {
+                fn __otel_auto_message(&self) -> String;
+            }
+            impl<T: ::std::fmt::Debug + ?Sized> __OtelAutoDebug for &__OtelAutoError<'_, T> {
+                fn __otel_auto_message(&self) -> String {
+                    format!("{:?}", self.0)
+                }
+            }
+            trait __OtelAutoEmpty {
+                fn __otel_auto_message(&self) -> String;
+            }
+            impl<T: ?Sized> __OtelAutoEmpty for __OtelAutoError<'_, T> {
+                fn __otel_auto_message(&self) -> String {
+                    String::new()
+                }
+            }
+            (&&&__OtelAutoError(e)).__otel_auto_message()
+        }
+    }
+}
+
+/// The `Error::source()` chain of the error `e`, also for `Box<dyn Error>` and `anyhow::Error`
+/// through `AsRef<dyn Error>`, or empty if `e` is none of them.
+fn error_source_token() -> TokenStream {
+    quote! {
+        {
+            struct __OtelAutoError<'a, T: ?Sized>(&'a T);
+            fn __otel_auto_chain(
+                e: &(dyn ::std::error::Error + '_),
+            ) -> Vec<::opentelemetry::StringValue> {
+                let mut source = Vec::new();
+                let mut cause = e.source();
+                while let Some(e) = cause {
+                    source.push(::opentelemetry::StringValue::from(e.to_string()));
+                    cause = e.source();
+                }
+                source
+            }
+            trait __OtelAutoSource {
+                fn __otel_auto_source(&self) -> Vec<::opentelemetry::StringValue>;
+            }
+            impl<T: ::std::error::Error> __OtelAutoSource for &&&__OtelAutoError<'_, T> {
+                fn __otel_auto_source(&self) -> Vec<::opentelemetry::StringValue> {
+                    __otel_auto_chain(self.0)
+                }
+            }
+            trait __OtelAutoAsRef {
+                fn __otel_auto_source(&self) -> Vec<::opentelemetry::StringValue>;
+            }
+            impl<T: AsRef<dyn ::std::error::Error> + ?Sized> __OtelAutoAsRef for &&__OtelAutoError<'_, T> {
+                fn __otel_auto_source(&self) -> Vec<::opentelemetry::StringValue> {
+                    __otel_auto_chain(self.0.as_ref())
+                }
+            }
+            trait __OtelAutoAsRefSend {
+                fn __otel_auto_source(&self) -> Vec<::opentelemetry::StringValue>;
+            }
+            impl<T> __OtelAutoAsRefSend for &__OtelAutoError<'_, T>
+            where
+                T: AsRef<dyn ::std::error::Error + Send + Sync> + ?Sized,
+            {
+                fn __otel_auto_source(&self) -> Vec<::opentelemetry::StringValue> {
+                    __otel_auto_chain(self.0.as_ref())
+                }
+            }
+            trait __OtelAutoNoSource {
+                fn __otel_auto_source(&self) -> Vec<::opentelemetry::StringValue>;
+            }
+            impl<T: ?Sized> __OtelAutoNoSource for __OtelAutoError<'_, T> {
+                fn __otel_auto_source(&self) -> Vec<::opentelemetry::StringValue> {
+                    Vec::new()
+                }
+            }
+            (&&&&__OtelAutoError(e)).__otel_auto_source()
+        }
+    }
+}
+
+/// Add `exception` event for the error `e`, following the semantic conventions.
+///
+/// The `Error::source()` chain is recorded as `auto_span.exception.causes`, a non-standard attribute,
+/// followed by `attributes`.
+fn exception_event_token(span_ident: &Ident, attributes: &[TokenStream]) -> TokenStream {
+    let message = error_message_token();
+    let source = error_source_token();
+    quote! {
+        #span_ident.add_event(
+            "exception",
+            vec![
+                ::opentelemetry::KeyValue::new("exception.type", ::std::any::type_name_of_val(e)),
+                ::opentelemetry::KeyValue::new("exception.message", #message),
+                ::opentelemetry::KeyValue::new(
+                    "auto_span.exception.causes",
+                    ::opentelemetry::Value::Array(::opentelemetry::Array::String(#source)),
+                ),
+                #(#attributes,)*
+            ],
+        );
+    }
+}
+
+fn otel_start_tracer_token<T: ToTokens + ?Sized>(name: &T) -> TokenStream {
+    quote! {
+        __otel_auto_with_tracer(|tracer| ::opentelemetry::trace::Tracer::start(tracer, #name))
+    }
+}
+
+fn otel_build_span_token<T: ToTokens + ?Sized>(name: &T, kind: &TokenStream) -> TokenStream {
+    quote! {
+        __otel_auto_with_tracer(|tracer| {
+            ::opentelemetry::trace::Tracer::build(
+                tracer,
+                ::opentelemetry::trace::SpanBuilder::from_name(#name).with_kind(#kind),
+            )
+        })
+    }
+}
+
+fn otel_attributes_token(attributes: &[TokenStream]) -> TokenStream {
+    if attributes.is_empty() {
+        return TokenStream::new();
+    }
+    quote! {
+        let __otel_auto_attributes = [#(#attributes),*];
+    }
+}
+
+/// Start the function span with the kind and the attributes, so that samplers can see them
+fn otel_start_span_token(
+    span_ident: &Ident,
+    name: &TokenStream,
+    kind: kind::SpanKind,
+    attributes: &[TokenStream],
+) -> TokenStream {
+    let kind = kind.token();
+    let mut builder = quote! {
+        ::opentelemetry::trace::SpanBuilder::from_name(#name).with_kind(#kind)
+    };
+    if !attributes.is_empty() {
+        builder.extend(quote! { .with_attributes(__otel_auto_attributes) });
+    }
+    quote! {
+        let #span_ident = __otel_auto_with_tracer(|tracer| {
+            ::opentelemetry::trace::Tracer::build(tracer, #builder)
+        });
     }
 }
 
@@ -111,47 +719,87 @@ fn otel_ctx_token(span_ident: &Ident) -> TokenStream {
     }
 }
 
-struct AutoSpanVisitor {
+struct AutoSpanVisitor<'a> {
     line_access: Option<LineAccess>,
+    aliases: &'a TypeAliases,
+    opt: &'a Opt,
     context: Vec<ReturnTypeContext>,
+    /// `db.system` of the variables, guessed from their types or initializers
+    db_systems: HashMap<String, &'static str>,
+    /// Variables of `sqlx::QueryBuilder`
+    query_builders: HashSet<String>,
+    /// Whether the error status is set by the returned value of the function
+    status_at_exit: bool,
 }
 
-#[derive(Copy, Clone)]
-enum ReturnTypeContext {
-    Unknown,
-    Result,
-    Option,
-}
-
-impl AutoSpanVisitor {
-    fn new(line_access: Option<LineAccess>) -> AutoSpanVisitor {
+impl<'a> AutoSpanVisitor<'a> {
+    fn new(
+        line_access: Option<LineAccess>,
+        aliases: &'a TypeAliases,
+        opt: &'a Opt,
+    ) -> AutoSpanVisitor<'a> {
         AutoSpanVisitor {
             line_access,
+            aliases,
+            opt,
             context: Vec::new(),
+            db_systems: HashMap::new(),
+            query_builders: HashSet::new(),
+            status_at_exit: false,
         }
     }
 
-    fn push_fn_context(&mut self, sig: &Signature) {
-        let rt = match &sig.output {
-            syn::ReturnType::Default => ReturnTypeContext::Unknown,
-            syn::ReturnType::Type(_, ty) => match ty.as_ref() {
-                syn::Type::Path(path) => {
-                    let name = path.path.segments.last().unwrap().ident.to_string();
-                    if name.contains("Result") {
-                        ReturnTypeContext::Result
-                    } else if name.contains("Option") {
-                        ReturnTypeContext::Option
-                    } else {
-                        ReturnTypeContext::Unknown
-                    }
-                }
-                _ => ReturnTypeContext::Unknown,
-            },
+    fn record_variables(&mut self, sig: &Signature) {
+        for input in &sig.inputs {
+            if let FnArg::Typed(pat) = input {
+                self.record_variable(&pat.pat, pat.ty.to_token_stream());
+            }
+        }
+    }
+
+    /// Record what the variable is from its type or initializer
+    fn record_variable(&mut self, pat: &Pat, tokens: TokenStream) {
+        let ident = match pat {
+            Pat::Ident(p) => p.ident.to_string(),
+            Pat::Type(p) => return self.record_variable(&p.pat, tokens),
+            _ => return,
         };
+        if has_ident(tokens.clone(), "QueryBuilder") {
+            self.query_builders.insert(ident.clone());
+        } else {
+            self.query_builders.remove(&ident);
+        }
+        match self.db_system(tokens) {
+            Some(system) => self.db_systems.insert(ident, system),
+            None => self.db_systems.remove(&ident),
+        };
+    }
+
+    /// Find `PgPool` or a variable already known to be a connection, such as `&mut conn`
+    fn db_system(&self, tokens: TokenStream) -> Option<&'static str> {
+        tokens.into_iter().find_map(|tt| match tt {
+            TokenTree::Ident(ident) => {
+                let ident = ident.to_string();
+                sql::db_system(&ident).or_else(|| self.db_systems.get(&ident).copied())
+            }
+            TokenTree::Group(g) => self.db_system(g.stream()),
+            _ => None,
+        })
+    }
+
+    fn visit_fn_body(&mut self, rt: ReturnTypeContext, block: &mut Block) {
         self.context.push(rt);
+        self.visit_block_mut(block);
+        self.pop_context();
+    }
+
+    /// Closures are known only if the return type is written explicitly.
+    pub fn push_closure_context(&mut self, output: &ReturnType) {
+        self.context.push(self.aliases.return_type_context(output));
     }
 
-    pub fn push_closure_context(&mut self) {
+    /// Blocks such as `async` or `try`, its output type is unknown.
+    pub fn push_block_context(&mut self) {
         self.context.push(ReturnTypeContext::Unknown);
     }
 
@@ -163,10 +811,253 @@ impl AutoSpanVisitor {
         *self.context.last().unwrap()
     }
 
-    fn handle_sqlx(&self, expr_await: &mut ExprAwait) -> bool {
-        let mut visitor = handle_sqlx::SqlxVisitor::new();
+    /// Returns the span name, the attributes and the body of the `db` span
+    fn handle_sqlx(
+        &self,
+        expr_await: &mut ExprAwait,
+    ) -> Option<(String, Vec<TokenStream>, TokenStream)> {
+        // the executor, `&pool` of `.fetch_one(&pool)`
+        let system = match expr_await.base.as_ref() {
+            Expr::MethodCall(call) => self.db_system(call.args.to_token_stream()),
+            _ => None,
+        }
+        .or_else(|| self.db_system(expr_await.base.to_token_stream()));
+
+        // `pool.begin().await`, only if the receiver is known to be a pool or a connection
+        if let Expr::MethodCall(call) = expr_await.base.as_ref() {
+            if let Some(name) = handle_sqlx::connection_span_name(call) {
+                let system = self.db_system(call.receiver.to_token_stream())?;
+                let attributes =
+                    vec![quote! { ::opentelemetry::KeyValue::new("db.system", #system) }];
+                return Some((name.to_string(), attributes, expr_await.to_token_stream()));
+            }
+        }
+
+        let mut visitor = handle_sqlx::SqlxVisitor::new(
+            self.opt.sql.unwrap_or_default(),
+            system,
+            &self.query_builders,
+        );
+        visitor.visit_expr_await_mut(expr_await);
+        if !visitor.is_mutate() {
+            return None;
+        }
+
+        let (name, attributes) = db_span(system, visitor.sql());
+
+        if self.opt.db_params {
+            handle_sqlx::record_binds(&mut expr_await.base);
+        }
+        let mut body = expr_await.to_token_stream();
+        if self.opt.db_rows {
+            if let Some(rows) = handle_sqlx::rows_token(&expr_await.base) {
+                body = quote! {
+                    {
+                        let __otel_auto_rows = #body;
+                        if let Ok(rows) = &__otel_auto_rows {
+                            ::opentelemetry::trace::get_active_span(|span| {
+                                if span.is_recording() {
+                                    span.set_attribute(#rows);
+                                }
+                            });
+                        }
+                        __otel_auto_rows
+                    }
+                };
+            }
+        }
+        Some((name, attributes, visitor.wrap(body)))
+    }
+
+    /// Returns the span name and the attributes of the `db` span
+    #[cfg(feature = "diesel")]
+    fn handle_diesel(&self, expr: &Expr) -> Option<(String, Vec<TokenStream>)> {
+        let Expr::MethodCall(call) = expr else {
+            return None;
+        };
+        let is_connection =
+            |arg: &Expr| !matches!(self.db_system(arg.to_token_stream()), None | Some("redis"));
+        let query = handle_diesel::diesel_query(call, &is_connection)?;
+        let system = self.db_system(call.args.to_token_stream());
+        let (name, mut attributes) = db_span(system, query.sql_str().as_deref());
+        let mode = self.opt.sql.unwrap_or_default();
+        if let Some(statement) = query
+            .sql
+            .and_then(|sql| sql::statement_token(&sql, mode, system))
+        {
+            attributes.push(quote! { ::opentelemetry::KeyValue::new("db.statement", #statement) });
+        }
+        Some((name, attributes))
+    }
+
+    #[cfg(not(feature = "diesel"))]
+    fn handle_diesel(&self, _expr: &Expr) -> Option<(String, Vec<TokenStream>)> {
+        None
+    }
+
+    /// Returns the span name and the attributes of the `db` span
+    #[cfg(feature = "sea-orm")]
+    fn handle_sea_orm(&self, expr_await: &mut ExprAwait) -> Option<(String, Vec<TokenStream>)> {
+        let system = match expr_await.base.as_ref() {
+            Expr::MethodCall(call) => self.db_system(call.to_token_stream()),
+            _ => None,
+        };
+        let mut visitor =
+            handle_sea_orm::SeaOrmVisitor::new(self.opt.sql.unwrap_or_default(), system);
+        visitor.visit_expr_await_mut(expr_await);
+        visitor.is_mutate().then(|| db_span(system, visitor.sql()))
+    }
+
+    #[cfg(not(feature = "sea-orm"))]
+    fn handle_sea_orm(&self, _expr_await: &mut ExprAwait) -> Option<(String, Vec<TokenStream>)> {
+        None
+    }
+
+    /// Returns the span name and the attributes of the redis span.
+    ///
+    /// `AsyncCommands` methods are recognized only if the receiver is known as a redis connection.
+    #[cfg(feature = "redis")]
+    fn handle_redis(&self, expr_await: &mut ExprAwait) -> Option<(String, Vec<TokenStream>)> {
+        let Expr::MethodCall(call) = expr_await.base.as_mut() else {
+            return None;
+        };
+        let operation = match handle_redis::redis_cmd(call) {
+            Some(operation) => {
+                if self.opt.db_params {
+                    handle_sqlx::record_chain_args(&mut call.receiver, "arg");
+                }
+                operation
+            }
+            None => {
+                let binding = handle_redis::local_binding(&call.receiver)?;
+                if self.db_system(binding.to_token_stream()) != Some("redis") {
+                    return None;
+                }
+                let operation = handle_redis::command_name(call)?;
+                if self.opt.db_params {
+                    for (n, arg) in call.args.iter_mut().enumerate() {
+                        handle_sqlx::record_param(arg, n);
+                    }
+                }
+                Some(operation)
+            }
+        };
+        let mut attributes = vec![quote! { ::opentelemetry::KeyValue::new("db.system", "redis") }];
+        if let Some(op) = &operation {
+            attributes.push(quote! { ::opentelemetry::KeyValue::new("db.operation.name", #op) });
+        }
+        Some((operation.unwrap_or_else(|| "redis".to_string()), attributes))
+    }
+
+    #[cfg(not(feature = "redis"))]
+    fn handle_redis(&self, _expr_await: &mut ExprAwait) -> Option<(String, Vec<TokenStream>)> {
+        None
+    }
+
+    /// Returns the span name, the attributes and the body of the HTTP client span
+    #[cfg(feature = "reqwest")]
+    fn handle_reqwest(
+        &self,
+        expr_await: &mut ExprAwait,
+    ) -> Option<(String, Vec<TokenStream>, TokenStream)> {
+        let request = handle_reqwest::http_request(&expr_await.base)?;
+        if self.opt.propagate {
+            handle_reqwest::inject_context(&mut expr_await.base);
+        }
+        let method = &request.method;
+        let mut attributes =
+            vec![quote! { ::opentelemetry::KeyValue::new("http.request.method", #method) }];
+        if let Some(url) = &request.url {
+            attributes.push(quote! { ::opentelemetry::KeyValue::new("url.full", #url) });
+        }
+        let body = quote! {
+            {
+                let __otel_auto_response = #expr_await;
+                ::opentelemetry::trace::get_active_span(|span| match &__otel_auto_response {
+                    _ if !span.is_recording() => {}
+                    Ok(response) => {
+                        let status = response.status().as_u16();
+                        span.set_attribute(::opentelemetry::KeyValue::new(
+                            "http.response.status_code",
+                            i64::from(status),
+                        ));
+                        if status >= 400 {
+                            span.set_status(::opentelemetry::trace::Status::error(""));
+                        }
+                    }
+                    Err(e) => span.set_status(::opentelemetry::trace::Status::error(e.to_string())),
+                });
+                __otel_auto_response
+            }
+        };
+        Some((format!("HTTP {}", method), attributes, body))
+    }
+
+    #[cfg(not(feature = "reqwest"))]
+    fn handle_reqwest(
+        &self,
+        _expr_await: &mut ExprAwait,
+    ) -> Option<(String, Vec<TokenStream>, TokenStream)> {
+        None
+    }
+
+    /// Returns the span name and the attributes of the `db` span
+    #[cfg(feature = "tokio-postgres")]
+    fn handle_tokio_postgres(
+        &self,
+        expr_await: &mut ExprAwait,
+    ) -> Option<(String, Vec<TokenStream>)> {
+        let is_client =
+            |receiver: &Expr| self.db_system(receiver.to_token_stream()) == Some("postgresql");
+        let mut visitor = handle_tokio_postgres::PostgresVisitor::new(
+            self.opt.sql.unwrap_or_default(),
+            &is_client,
+        );
         visitor.visit_expr_await_mut(expr_await);
-        visitor.is_mutate()
+        visitor
+            .is_mutate()
+            .then(|| db_span(Some("postgresql"), visitor.sql()))
+    }
+
+    #[cfg(not(feature = "tokio-postgres"))]
+    fn handle_tokio_postgres(
+        &self,
+        _expr_await: &mut ExprAwait,
+    ) -> Option<(String, Vec<TokenStream>)> {
+        None
+    }
+
+    /// Carry the current context into `tokio::spawn` and so on, with a `spawn` span if `spawn_span`
+    fn handle_spawn(&self, call: &mut ExprCall, line_info: Option<(i64, String)>) {
+        let Some(kind) = handle_spawn::spawn_kind(call) else {
+            return;
+        };
+        let ctx = if self.opt.spawn_span {
+            let span_ident = self.span_ident();
+            let mut set_line_info = TokenStream::new();
+            add_line_info(&mut set_line_info, &span_ident, line_info);
+            let set_line_info = if_recording(&span_ident, set_line_info);
+            quote! {
+                {
+                    #[allow(unused_import)]
+                    use ::opentelemetry::trace::{Span as _};
+                    let __otel_auto_ctx = ::opentelemetry::Context::current();
+                    #[allow(unused_mut)]
+                    let mut #span_ident = __otel_auto_with_tracer(|tracer| {
+                        ::opentelemetry::trace::Tracer::start_with_context(
+                            tracer,
+                            "spawn",
+                            &__otel_auto_ctx,
+                        )
+                    });
+                    #set_line_info
+                    ::opentelemetry::trace::TraceContextExt::with_span(&__otel_auto_ctx, #span_ident)
+                }
+            }
+        } else {
+            quote! { ::opentelemetry::Context::current() }
+        };
+        handle_spawn::with_context(call, kind, ctx);
     }
 
     fn get_line_info(&self, span: Span) -> Option<(i64, String)> {
@@ -178,39 +1069,103 @@ impl AutoSpanVisitor {
     }
 }
 
+/// Span name and attributes of a `db` span, named after the SQL if known
+fn db_span(system: Option<&str>, sql: Option<&str>) -> (String, Vec<TokenStream>) {
+    let summary = sql.map(sql::summary).unwrap_or_default();
+    let attributes = db_attributes(system, &summary);
+    let name = summary.span_name().unwrap_or_else(|| "db".to_string());
+    (name, attributes)
+}
+
+fn db_attributes(system: Option<&str>, summary: &sql::Summary) -> Vec<TokenStream> {
+    let mut attributes = Vec::new();
+    if let Some(system) = system {
+        attributes.push(quote! { ::opentelemetry::KeyValue::new("db.system", #system) });
+    }
+    if let Some(op) = &summary.operation {
+        attributes.push(quote! { ::opentelemetry::KeyValue::new("db.operation.name", #op) });
+    }
+    if let Some(table) = &summary.collection {
+        attributes.push(quote! { ::opentelemetry::KeyValue::new("db.collection.name", #table) });
+    }
+    attributes
+}
+
+/// Run `tokens` only if the span is recording, to skip formatting the values of a dropped span
+fn if_recording(span_ident: &Ident, tokens: TokenStream) -> TokenStream {
+    if tokens.is_empty() {
+        return tokens;
+    }
+    quote! {
+        if #span_ident.is_recording() {
+            #tokens
+        }
+    }
+}
+
+/// Add `code.lineno` and `code.line`, must be in the tokens checked by `if_recording`
 fn add_line_info(tokens: &mut TokenStream, span_ident: &Ident, line_info: Option<(i64, String)>) {
-    if let Some((lineno, line)) = line_info {
+    for kv in line_info_key_values(line_info) {
         tokens.extend(quote! {
-            #span_ident.set_attribute(::opentelemetry::KeyValue::new("code.lineno", #lineno));
-            #span_ident.set_attribute(::opentelemetry::KeyValue::new("code.line", #line));
+            #span_ident.set_attribute(#kv);
         });
     }
 }
 
-impl VisitMut for AutoSpanVisitor {
+fn line_info_key_values(line_info: Option<(i64, String)>) -> Vec<TokenStream> {
+    match line_info {
+        Some((lineno, line)) => vec![
+            quote! { ::opentelemetry::KeyValue::new("code.lineno", #lineno) },
+            quote! { ::opentelemetry::KeyValue::new("code.line", #line) },
+        ],
+        None => Vec::new(),
+    }
+}
+
+impl VisitMut for AutoSpanVisitor<'_> {
     fn visit_expr_mut(&mut self, i: &mut Expr) {
         let span = i.span();
 
         let span_ident = self.span_ident();
-        let new_span = |name, line_info, expr| {
-            let start_tracer = otel_start_tracer_token(name);
+        let new_span = |start_tracer, attributes: Vec<TokenStream>, line_info, expr, is_async| {
             let current_with_span = otel_ctx_token(&span_ident);
-            let mut tokens = quote! {
+            let mut set_attributes = TokenStream::new();
+            for kv in attributes {
+                set_attributes.extend(quote! {
+                    #span_ident.set_attribute(#kv);
+                });
+            }
+            add_line_info(&mut set_attributes, &span_ident, line_info);
+            let set_attributes = if_recording(&span_ident, set_attributes);
+            let tokens = quote! {
                 #[allow(unused_import)]
                 use ::opentelemetry::trace::{Span as _};
                 #[allow(unused_mut)]
                 let mut #span_ident = #start_tracer;
+                #set_attributes
             };
-            add_line_info(&mut tokens, &span_ident, line_info);
-            let tokens = quote_spanned! {
-                span => {
-                    ::opentelemetry::trace::FutureExt::with_context(
-                        async { #expr },
-                        {
+            let tokens = if is_async {
+                quote_spanned! {
+                    span => {
+                        ::opentelemetry::trace::FutureExt::with_context(
+                            async { #expr },
+                            {
+                                #tokens
+                                #current_with_span
+                            }
+                        ).await
+                    }
+                }
+            } else {
+                quote_spanned! {
+                    span => {
+                        let __otel_auto_ctx = {
                             #tokens
                             #current_with_span
-                        }
-                    ).await
+                        };
+                        let __otel_auto_guard = __otel_auto_ctx.attach();
+                        #expr
+                    }
                 }
             };
             syn::parse2(tokens).unwrap()
@@ -218,53 +1173,138 @@ impl VisitMut for AutoSpanVisitor {
 
         match i {
             Expr::Await(expr) => {
-                if self.handle_sqlx(expr) {
-                    *i = new_span("db", self.get_line_info(span), expr);
+                let line_info = self.get_line_info(span);
+                if let Some((name, attributes, body)) = self.handle_sqlx(expr) {
+                    let start_tracer = otel_start_tracer_token(&name);
+                    *i = new_span(start_tracer, attributes, line_info, body, true);
+                } else if let Some((name, attributes, body)) = self.handle_reqwest(expr) {
+                    let kind = quote! { ::opentelemetry::trace::SpanKind::Client };
+                    let start_tracer = otel_build_span_token(&name, &kind);
+                    *i = new_span(start_tracer, attributes, line_info, body, true);
+                } else if let Some((name, attributes)) = self
+                    .handle_diesel(&expr.base)
+                    .or_else(|| self.handle_sea_orm(expr))
+                    .or_else(|| self.handle_tokio_postgres(expr))
+                    .or_else(|| self.handle_redis(expr))
+                {
+                    let start_tracer = otel_start_tracer_token(&name);
+                    let body = expr.to_token_stream();
+                    *i = new_span(start_tracer, attributes, line_info, body, true);
                 } else {
                     syn::visit_mut::visit_expr_await_mut(self, expr);
                 }
             }
+            Expr::MethodCall(_) => {
+                if let Some((name, attributes)) = self.handle_diesel(i) {
+                    let start_tracer = otel_start_tracer_token(&name);
+                    let body = i.to_token_stream();
+                    *i = new_span(
+                        start_tracer,
+                        attributes,
+                        self.get_line_info(span),
+                        body,
+                        false,
+                    );
+                } else {
+                    syn::visit_mut::visit_expr_mut(self, i);
+                }
+            }
+            Expr::Call(call) => {
+                syn::visit_mut::visit_expr_call_mut(self, call);
+                self.handle_spawn(call, self.get_line_info(span));
+            }
             _ => syn::visit_mut::visit_expr_mut(self, i),
         };
     }
 
+    fn visit_local_mut(&mut self, i: &mut Local) {
+        if let Some(init) = &i.init {
+            let mut tokens = init.expr.to_token_stream();
+            if let Pat::Type(p) = &i.pat {
+                tokens = p.ty.to_token_stream();
+            }
+            self.record_variable(&i.pat, tokens);
+        }
+        syn::visit_mut::visit_local_mut(self, i);
+    }
+
     fn visit_expr_closure_mut(&mut self, i: &mut ExprClosure) {
-        self.push_closure_context();
+        self.push_closure_context(&i.output);
         syn::visit_mut::visit_expr_closure_mut(self, i);
         self.pop_context();
     }
 
+    fn visit_expr_async_mut(&mut self, i: &mut ExprAsync) {
+        self.push_block_context();
+        syn::visit_mut::visit_expr_async_mut(self, i);
+        self.pop_context();
+    }
+
+    fn visit_expr_try_block_mut(&mut self, i: &mut ExprTryBlock) {
+        self.push_block_context();
+        syn::visit_mut::visit_expr_try_block_mut(self, i);
+        self.pop_context();
+    }
+
     fn visit_expr_try_mut(&mut self, i: &mut ExprTry) {
         syn::visit_mut::visit_expr_try_mut(self, i);
 
-        if let ReturnTypeContext::Result = self.current_context() {
-            let span_ident = self.span_ident();
-            let span = i.expr.span();
-            let inner = i.expr.as_ref();
-            let mut tokens = quote! {
-                #span_ident.set_status(::opentelemetry::trace::Status::error(format!("{}", e)));
-            };
-            add_line_info(&mut tokens, &span_ident, self.get_line_info(span));
-            i.expr = Box::new(
-                syn::parse2(quote_spanned! {
-                    span => #inner.map_err(|e| {
+        let span_ident = self.span_ident();
+        let span = i.expr.span();
+        let inner = i.expr.as_ref();
+        match self.current_context() {
+            ReturnTypeContext::Result => {
+                let mut tokens = TokenStream::new();
+                let line_info = self.get_line_info(span);
+                if self.context.len() > 1 {
+                    // the error may be handled outside the closure, so only record an event
+                    let attributes = line_info_key_values(line_info);
+                    tokens.extend(exception_event_token(&span_ident, &attributes));
+                } else {
+                    // at the function level, the returned `Err` sets the status
+                    if !self.status_at_exit {
+                        let message = error_message_token();
+                        tokens.extend(quote! {
+                            #span_ident.set_status(::opentelemetry::trace::Status::error(#message));
+                        });
+                        if self.opt.exception_events {
+                            tokens.extend(exception_event_token(&span_ident, &[]));
+                        }
+                    }
+                    add_line_info(&mut tokens, &span_ident, line_info);
+                }
+                if tokens.is_empty() {
+                    return;
+                }
+                let tokens = if_recording(&span_ident, tokens);
+                *i.expr = syn::parse2(quote_spanned! {
+                    span => #inner.inspect_err(|#[allow(unused_variables)] e| {
                         ::opentelemetry::trace::get_active_span(|__otel_auto_span| {
                             #tokens
                         });
-                        e
                     })
                 })
-                .unwrap(),
-            );
+                .unwrap();
+            }
+            ReturnTypeContext::Option => {
+                let attributes = line_info_key_values(self.get_line_info(span));
+                *i.expr = syn::parse2(quote_spanned! {
+                    span => #inner.or_else(|| {
+                        ::opentelemetry::trace::get_active_span(|__otel_auto_span| {
+                            if #span_ident.is_recording() {
+                                #span_ident.add_event("option.none", vec![#(#attributes),*]);
+                            }
+                        });
+                        None
+                    })
+                })
+                .unwrap();
+            }
+            ReturnTypeContext::Unknown => {}
         }
     }
 
-    fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        self.push_fn_context(&i.sig);
-        if self.context.len() == 1 {
-            // skip inner function, because `span` is not shared
-            syn::visit_mut::visit_item_fn_mut(self, i);
-        }
-        self.pop_context();
+    fn visit_item_mut(&mut self, _: &mut Item) {
+        // skip inner items, because `span` is not shared
     }
 }
@@ -0,0 +1,89 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+pub const DEFAULT_TEMPLATE: &str = "fn:{fn}";
+
+#[derive(Debug, Eq, PartialEq)]
+enum Piece {
+    Lit(String),
+    Module,
+}
+
+/// Build the span name expression from a template.
+///
+/// `{fn}` is replaced with the function name and `{module}` with `module_path!()`.
+/// Other placeholders are kept as is, so a route such as `"GET /user/{id}"` can be used verbatim.
+pub fn span_name(template: &str, fn_name: &str) -> TokenStream {
+    let pieces = parse_template(template, fn_name);
+    if pieces.iter().all(|p| matches!(p, Piece::Lit(_))) {
+        let name = pieces
+            .into_iter()
+            .map(|p| match p {
+                Piece::Lit(s) => s,
+                Piece::Module => unreachable!(),
+            })
+            .collect::<String>();
+        return quote! { #name };
+    }
+    let parts = pieces.into_iter().map(|p| match p {
+        Piece::Lit(s) => quote! { #s },
+        Piece::Module => quote! { ::core::module_path!() },
+    });
+    quote! { ::core::concat!(#(#parts),*) }
+}
+
+fn parse_template(template: &str, fn_name: &str) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut lit = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        lit.push_str(&rest[..start]);
+        match &rest[start + 1..start + len] {
+            "fn" => lit.push_str(fn_name),
+            "module" => {
+                if !lit.is_empty() {
+                    pieces.push(Piece::Lit(std::mem::take(&mut lit)));
+                }
+                pieces.push(Piece::Module);
+            }
+            _ => lit.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    lit.push_str(rest);
+    if !lit.is_empty() || pieces.is_empty() {
+        pieces.push(Piece::Lit(lit));
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit(s: &str) -> Piece {
+        Piece::Lit(s.to_owned())
+    }
+
+    #[test]
+    fn test_parse_template() {
+        assert_eq!(parse_template(DEFAULT_TEMPLATE, "f"), vec![lit("fn:f")]);
+        assert_eq!(parse_template("custom", "f"), vec![lit("custom")]);
+        assert_eq!(parse_template("", "f"), vec![lit("")]);
+        assert_eq!(
+            parse_template("{module}::{fn}", "f"),
+            vec![Piece::Module, lit("::f")],
+        );
+        assert_eq!(
+            parse_template("GET /user/{id}", "f"),
+            vec![lit("GET /user/{id}")],
+        );
+        assert_eq!(
+            parse_template("a{module}b{", "f"),
+            vec![lit("a"), Piece::Module, lit("b{")],
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::{collections::HashMap, path::Path};
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+use syn::{
+    visit_mut::VisitMut, Block, File, GenericArgument, Item, PathArguments, ReturnType, Stmt, Type,
+    TypeParamBound,
+};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ReturnTypeContext {
+    Unknown,
+    Result,
+    Option,
+}
+
+/// Type aliases declared in the source file, used to classify the return type.
+#[derive(Default)]
+pub struct TypeAliases(HashMap<String, Type>);
+
+impl TypeAliases {
+    pub fn from_path(path: Option<&Path>) -> TypeAliases {
+        path.and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| syn::parse_file(&content).ok())
+            .map(|file| TypeAliases::from_file(&file))
+            .unwrap_or_default()
+    }
+
+    pub fn from_file(file: &File) -> TypeAliases {
+        let mut aliases = TypeAliases::default();
+        aliases.collect(&file.items);
+        aliases
+    }
+
+    fn collect(&mut self, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Type(alias) => {
+                    self.0
+                        .insert(alias.ident.to_string(), alias.ty.as_ref().clone());
+                }
+                Item::Mod(m) => {
+                    if let Some((_, items)) = &m.content {
+                        self.collect(items);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Context of `?` in a closure, see `try_context`
+    pub fn return_type_context(&self, output: &ReturnType) -> ReturnTypeContext {
+        match output {
+            ReturnType::Default => ReturnTypeContext::Unknown,
+            ReturnType::Type(_, ty) => self.try_context(ty),
+        }
+    }
+
+    /// Whether the type is exactly `Result` or `Option`, or an alias of them.
+    ///
+    /// The returned value is matched with `Ok`/`Err`, so other types must not be `Result`.
+    pub fn type_context(&self, ty: &Type) -> ReturnTypeContext {
+        self.type_context_with_depth(ty, 0, false)
+    }
+
+    /// Context of `?`, where the type is guessed by the name such as `AppResult`.
+    ///
+    /// `?` is only valid for `Result` or `Option` anyway, so this also covers the aliases
+    /// imported from other files.
+    pub fn try_context(&self, ty: &Type) -> ReturnTypeContext {
+        self.type_context_with_depth(ty, 0, true)
+    }
+
+    fn type_context_with_depth(&self, ty: &Type, depth: usize, by_name: bool) -> ReturnTypeContext {
+        match ty {
+            Type::Path(path) => {
+                let Some(seg) = path.path.segments.last() else {
+                    return ReturnTypeContext::Unknown;
+                };
+                let name = seg.ident.to_string();
+                // `type Result<T> = std::result::Result<T, Error>` is resolved by the name
+                if path.path.segments.len() == 1 && depth < MAX_ALIAS_DEPTH {
+                    if let Some(alias) = self.0.get(&name) {
+                        return self.type_context_with_depth(alias, depth + 1, by_name);
+                    }
+                }
+                if name == "Result" || by_name && name.ends_with("Result") {
+                    ReturnTypeContext::Result
+                } else if name == "Option" || by_name && name.ends_with("Option") {
+                    ReturnTypeContext::Option
+                } else {
+                    ReturnTypeContext::Unknown
+                }
+            }
+            Type::Paren(p) => self.type_context_with_depth(&p.elem, depth, by_name),
+            Type::Group(g) => self.type_context_with_depth(&g.elem, depth, by_name),
+            _ => ReturnTypeContext::Unknown,
+        }
+    }
+}
+
+const MAX_ALIAS_DEPTH: usize = 8;
+
+/// `T` of future types, such as `impl Future<Output = T>`, `Pin<Box<dyn Future<Output = T> + Send>>`
+/// and `BoxFuture<'a, T>`
+pub fn future_output(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Path(path) => {
+            let seg = path.path.segments.last()?;
+            let PathArguments::AngleBracketed(args) = &seg.arguments else {
+                return None;
+            };
+            let mut types = args.args.iter().filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            });
+            if seg.ident == "Pin" || seg.ident == "Box" {
+                types.find_map(future_output)
+            } else if seg.ident == "BoxFuture" || seg.ident == "LocalBoxFuture" {
+                types.next()
+            } else {
+                None
+            }
+        }
+        Type::TraitObject(obj) => bounds_future_output(obj.bounds.iter()),
+        Type::ImplTrait(imp) => bounds_future_output(imp.bounds.iter()),
+        Type::Paren(p) => future_output(&p.elem),
+        Type::Group(g) => future_output(&g.elem),
+        _ => None,
+    }
+}
+
+fn bounds_future_output<'a, I>(mut bounds: I) -> Option<&'a Type>
+where
+    I: Iterator<Item = &'a TypeParamBound>,
+{
+    bounds.find_map(|bound| {
+        let TypeParamBound::Trait(bound) = bound else {
+            return None;
+        };
+        let seg = bound.path.segments.last()?;
+        if seg.ident != "Future" {
+            return None;
+        }
+        let PathArguments::AngleBracketed(args) = &seg.arguments else {
+            return None;
+        };
+        args.args.iter().find_map(|arg| match arg {
+            GenericArgument::AssocType(assoc) if assoc.ident == "Output" => Some(&assoc.ty),
+            _ => None,
+        })
+    })
+}
+
+/// Identifier bound to the returned value while `inspect` runs.
+pub fn ret_ident() -> Ident {
+    Ident::new("__otel_auto_ret", Span::call_site())
+}
+
+/// Type to annotate the returned value with, if it can be written in `let` statement.
+///
+/// `None` of `ty` means `()`.
+/// `impl Trait` is replaced with `_`, so that the rest of the type, such as the error type of
+/// `Result<impl Responder, E>`, is still known to the inspection.
+pub fn annotation_type(ty: Option<&Type>) -> Option<Type> {
+    struct ImplTraitReplacer;
+    impl VisitMut for ImplTraitReplacer {
+        fn visit_type_mut(&mut self, ty: &mut Type) {
+            if matches!(ty, Type::ImplTrait(_)) {
+                *ty = syn::parse_quote! { _ };
+            } else {
+                syn::visit_mut::visit_type_mut(self, ty);
+            }
+        }
+    }
+    match ty {
+        None => Some(syn::parse_quote! { () }),
+        Some(Type::Never(_) | Type::Infer(_) | Type::ImplTrait(_)) => None,
+        Some(ty) => {
+            let mut ty = ty.clone();
+            ImplTraitReplacer.visit_type_mut(&mut ty);
+            Some(ty)
+        }
+    }
+}
+
+/// Run `inspect` for the value returned from the body.
+///
+/// The whole body is evaluated first, so the value is inspected whichever way it is returned,
+/// including `return` and `?` expanded from macros.
+/// A sync body is run in a closure, and an async body in an `async` block.
+pub fn inspect_return(
+    stmts: &mut Vec<Stmt>,
+    is_async: bool,
+    ty: Option<&Type>,
+    inspect: &TokenStream,
+) {
+    let ret = ret_ident();
+    let block: Block = if is_async {
+        let ty = ty.map(|ty| quote! { : #ty });
+        syn::parse_quote! {{
+            let #ret #ty = async { #(#stmts)* }.await;
+            #inspect
+            #ret
+        }}
+    } else {
+        // `FnOnce` lets the body return the references borrowed from the arguments
+        let ty = match ty {
+            Some(ty) => quote! { ::<#ty, _> },
+            None => TokenStream::new(),
+        };
+        syn::parse_quote! {{
+            fn __otel_auto_call<R, F: FnOnce() -> R>(f: F) -> R {
+                f()
+            }
+            let #ret = __otel_auto_call #ty (move || { #(#stmts)* });
+            #inspect
+            #ret
+        }}
+    };
+    *stmts = block.stmts;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_type_context() {
+        let file = syn::parse_file(
+            r#"
+type AppResult<T> = Result<T, AppError>;
+type Fallible<T> = AppResult<T>;
+mod inner {
+    pub type Maybe<T> = Option<T>;
+}
+"#,
+        )
+        .unwrap();
+        let aliases = TypeAliases::from_file(&file);
+        let t = |s| aliases.type_context(&syn::parse_str(s).unwrap());
+        assert_eq!(t("Result<i32, Error>"), ReturnTypeContext::Result);
+        assert_eq!(t("std::io::Result<()>"), ReturnTypeContext::Result);
+        assert_eq!(t("AppResult<i32>"), ReturnTypeContext::Result);
+        assert_eq!(t("Fallible<i32>"), ReturnTypeContext::Result);
+        assert_eq!(t("Option<i32>"), ReturnTypeContext::Option);
+        assert_eq!(t("Maybe<i32>"), ReturnTypeContext::Option);
+        assert_eq!(t("ResultSet"), ReturnTypeContext::Unknown);
+        assert_eq!(t("SqliteQueryResult"), ReturnTypeContext::Unknown);
+        assert_eq!(t("ImportedResult<i32>"), ReturnTypeContext::Unknown);
+        let t = |s| aliases.try_context(&syn::parse_str(s).unwrap());
+        assert_eq!(t("ImportedResult<i32>"), ReturnTypeContext::Result);
+        assert_eq!(t("Fallible<i32>"), ReturnTypeContext::Result);
+        assert_eq!(t("MyOption"), ReturnTypeContext::Option);
+        assert_eq!(t("(Result<i32, Error>)"), ReturnTypeContext::Result);
+        assert_eq!(t("Vec<Result<i32, Error>>"), ReturnTypeContext::Unknown);
+    }
+
+    #[test]
+    fn test_future_output() {
+        let t = |s| {
+            let ty: Type = syn::parse_str(s).unwrap();
+            future_output(&ty).map(|ty| quote! { #ty }.to_string())
+        };
+        let result = Some("Result < i32 , Error >".to_owned());
+        assert_eq!(t("impl Future<Output = Result<i32, Error>>"), result);
+        assert_eq!(
+            t("impl std::future::Future<Output = Result<i32, Error>> + Send + 'a"),
+            result
+        );
+        assert_eq!(
+            t("Pin<Box<dyn Future<Output = Result<i32, Error>> + Send + 'a>>"),
+            result
+        );
+        assert_eq!(t("Box<dyn Future<Output = Result<i32, Error>>>"), result);
+        assert_eq!(t("BoxFuture<'a, Result<i32, Error>>"), result);
+        assert_eq!(t("Result<i32, Error>"), None);
+        assert_eq!(t("impl Iterator<Item = i32>"), None);
+    }
+
+    #[test]
+    fn test_annotation_type() {
+        let t = |s| {
+            annotation_type(Some(&syn::parse_str(s).unwrap())).map(|ty| quote! { #ty }.to_string())
+        };
+        assert_eq!(
+            t("Result<i32, Error>"),
+            Some("Result < i32 , Error >".to_owned())
+        );
+        assert_eq!(t("&'a str"), Some("& 'a str".to_owned()));
+        assert_eq!(
+            t("Result<impl Responder, Error>"),
+            Some("Result < _ , Error >".to_owned())
+        );
+        assert_eq!(t("impl Responder"), None);
+        assert_eq!(t("!"), None);
+        assert_eq!(
+            annotation_type(None).map(|ty| quote! { #ty }.to_string()),
+            Some("()".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_inspect_return() {
+        let mut block: syn::Block = syn::parse_quote! {{
+            if x {
+                return Err(1);
+            }
+            Ok(2)
+        }};
+        let ty: Type = syn::parse_quote! { Result<i32, i32> };
+        inspect_return(&mut block.stmts, false, Some(&ty), &quote! { inspect(); });
+        let expected = quote! {
+            fn __otel_auto_call<R, F: FnOnce() -> R>(f: F) -> R {
+                f()
+            }
+            let __otel_auto_ret = __otel_auto_call::<Result<i32, i32>, _>(move || {
+                if x {
+                    return Err(1);
+                }
+                Ok(2)
+            });
+            inspect();
+            __otel_auto_ret
+        };
+        // `||` is printed as `| |` after parsing
+        let expected: syn::Block = syn::parse_quote! {{ #expected }};
+        let (stmts, expected) = (&block.stmts, &expected.stmts);
+        assert_eq!(
+            quote! { #(#stmts)* }.to_string(),
+            quote! { #(#expected)* }.to_string()
+        );
+    }
+
+    #[test]
+    fn test_inspect_return_async() {
+        let mut block: syn::Block = syn::parse_quote! {{ Some(x? + 1) }};
+        inspect_return(&mut block.stmts, true, None, &quote! { inspect(); });
+        let expected = quote! {
+            let __otel_auto_ret = async { Some(x? + 1) }.await;
+            inspect();
+            __otel_auto_ret
+        };
+        let stmts = &block.stmts;
+        assert_eq!(quote! { #(#stmts)* }.to_string(), expected.to_string());
+    }
+}
@@ -0,0 +1,550 @@
+//! Lightweight SQL parsing done at macro expansion time.
+//!
+//! This is not a SQL parser: it only looks at the first few tokens of the statement
+//! to find the operation and the table, as needed for the span name.
+
+use darling::{Error, FromMeta};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Expr, ExprLit, Lit, LitStr};
+
+/// How to record the SQL string as `db.statement`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlMode {
+    Raw,
+    /// Replace the literals with `?`
+    Sanitize,
+    Off,
+}
+
+impl Default for SqlMode {
+    fn default() -> Self {
+        if cfg!(feature = "sanitize-sql") {
+            SqlMode::Sanitize
+        } else {
+            SqlMode::Raw
+        }
+    }
+}
+
+impl FromMeta for SqlMode {
+    fn from_string(value: &str) -> darling::Result<Self> {
+        match value {
+            "raw" => Ok(SqlMode::Raw),
+            "sanitize" => Ok(SqlMode::Sanitize),
+            "off" => Ok(SqlMode::Off),
+            _ => Err(Error::unknown_value(value)),
+        }
+    }
+}
+
+/// The value of `db.statement` from the SQL expression, which is a literal or a constant.
+///
+/// `system` is the `db.system` if known, which decides whether `"..."` is an identifier to keep.
+pub fn statement_token(sql: &Expr, mode: SqlMode, system: Option<&str>) -> Option<TokenStream> {
+    match (sql, mode) {
+        (_, SqlMode::Off) => None,
+        (
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(s), ..
+            }),
+            SqlMode::Sanitize,
+        ) => {
+            let sanitized = LitStr::new(&sanitize(&s.value(), system), s.span());
+            Some(quote! { #sanitized })
+        }
+        (Expr::Lit(_), SqlMode::Raw) => Some(quote! { #sql }),
+        // a constant or a variable of `&str`
+        (_, SqlMode::Raw) => Some(quote! { ::std::string::ToString::to_string(#sql) }),
+        // the value is unknown at compile time
+        (_, SqlMode::Sanitize) => None,
+    }
+}
+
+/// Record `db.statement` on the active span, and then evaluate `expr`
+pub fn with_statement(expr: &mut Expr, statement: TokenStream) {
+    *expr = syn::parse_quote! {
+        {
+            ::opentelemetry::trace::get_active_span(|span| {
+                if span.is_recording() {
+                    span.set_attribute(::opentelemetry::KeyValue::new("db.statement", #statement));
+                }
+            });
+            #expr
+        }
+    };
+}
+
+/// `db.system` guessed from an identifier such as `PgPool`, `MySqlConnection` or `sqlite`
+pub fn db_system(ident: &str) -> Option<&'static str> {
+    // diesel-async
+    let ident = ident.strip_prefix("Async").unwrap_or(ident);
+    let prefixed = |prefix: &str| {
+        ident
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_uppercase))
+    };
+    if prefixed("MySql") || prefixed("Mysql") || ident == "mysql" {
+        Some("mysql")
+    } else if prefixed("Pg")
+        || prefixed("Postgres")
+        || ident == "postgres"
+        || ident.ends_with("_postgres")
+    {
+        Some("postgresql")
+    } else if prefixed("Sqlite") || ident == "sqlite" {
+        Some("sqlite")
+    } else if prefixed("Mssql") || ident == "mssql" {
+        Some("mssql")
+    } else if prefixed("Redis")
+        || ident == "redis"
+        || ident.ends_with("_redis")
+        || ident == "MultiplexedConnection"
+    {
+        Some("redis")
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// Bare word, keyword or (possibly quoted, dotted) identifier
+    Word {
+        text: String,
+        quoted: bool,
+    },
+    Punct(char),
+    /// String or numeric literal
+    Literal,
+}
+
+fn tokenize(sql: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '-' => {
+                chars.next();
+                if chars.peek() == Some(&'-') {
+                    for c in chars.by_ref() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                } else {
+                    tokens.push(Token::Punct('-'));
+                }
+            }
+            '/' => {
+                chars.next();
+                if chars.peek() == Some(&'*') {
+                    chars.next();
+                    let mut prev = ' ';
+                    for c in chars.by_ref() {
+                        if prev == '*' && c == '/' {
+                            break;
+                        }
+                        prev = c;
+                    }
+                } else {
+                    tokens.push(Token::Punct('/'));
+                }
+            }
+            '\'' => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == '\'' {
+                        if chars.peek() == Some(&'\'') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                tokens.push(Token::Literal);
+            }
+            '0'..='9' => {
+                while chars
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '.')
+                {
+                    chars.next();
+                }
+                tokens.push(Token::Literal);
+            }
+            '"' | '`' | '[' => tokens.push(read_word(&mut chars)),
+            c if c.is_alphabetic() || c == '_' => tokens.push(read_word(&mut chars)),
+            c => {
+                chars.next();
+                tokens.push(Token::Punct(c));
+            }
+        }
+    }
+    tokens
+}
+
+/// Read `a`, `"a"`, `` `a` ``, `[a]` and the dotted form of them such as `schema."table"`.
+fn read_word(chars: &mut std::iter::Peekable<std::str::Chars>) -> Token {
+    let mut text = String::new();
+    let mut quoted = false;
+    loop {
+        match chars.peek() {
+            Some(&open @ ('"' | '`' | '[')) => {
+                let close = if open == '[' { ']' } else { open };
+                chars.next();
+                for c in chars.by_ref() {
+                    if c == close {
+                        break;
+                    }
+                    text.push(c);
+                }
+                quoted = true;
+            }
+            _ => {
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' || c == '$' {
+                        text.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            text.push('.');
+        } else {
+            break;
+        }
+    }
+    Token::Word { text, quoted }
+}
+
+fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+    matches!(token, Some(Token::Word { text, quoted: false }) if text.eq_ignore_ascii_case(keyword))
+}
+
+/// The operation and the table of a SQL statement
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// `db.operation.name`, such as `SELECT`
+    pub operation: Option<String>,
+    /// `db.collection.name`, only if a single table is found
+    pub collection: Option<String>,
+}
+
+impl Summary {
+    /// `{operation} {collection}` as recommended by the semantic conventions
+    pub fn span_name(&self) -> Option<String> {
+        match (&self.operation, &self.collection) {
+            (Some(op), Some(table)) => Some(format!("{} {}", op, table)),
+            (Some(op), None) => Some(op.clone()),
+            _ => None,
+        }
+    }
+}
+
+pub fn summary(sql: &str) -> Summary {
+    let tokens = tokenize(sql);
+    let operation = match tokens.first() {
+        Some(Token::Word {
+            text,
+            quoted: false,
+        }) => text.to_ascii_uppercase(),
+        _ => return Summary::default(),
+    };
+    let collection = match operation.as_str() {
+        "SELECT" | "DELETE" => top_level_keyword(&tokens, "FROM").and_then(|i| table(&tokens, i)),
+        "INSERT" | "REPLACE" => top_level_keyword(&tokens, "INTO").and_then(|i| table(&tokens, i)),
+        "UPDATE" => {
+            let i = if is_keyword(tokens.get(1), "OR") {
+                3
+            } else {
+                1
+            };
+            table(&tokens, i)
+        }
+        "CREATE" | "DROP" | "ALTER" | "TRUNCATE" => {
+            top_level_keyword(&tokens, "TABLE").and_then(|i| {
+                let mut i = i;
+                // IF [NOT] EXISTS
+                if is_keyword(tokens.get(i), "IF") {
+                    i += 1;
+                    if is_keyword(tokens.get(i), "NOT") {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                table(&tokens, i)
+            })
+        }
+        _ => None,
+    };
+    Summary {
+        operation: Some(operation),
+        collection,
+    }
+}
+
+/// Whether `"..."` is a quoted identifier, rather than a string literal as in MySQL
+fn quotes_identifier(system: Option<&str>) -> bool {
+    matches!(system, Some("postgresql" | "sqlite" | "mssql"))
+}
+
+/// Replace string and numeric literals with `?`.
+///
+/// Placeholders such as `$1` and quoted identifiers are kept.
+/// `"..."` is masked as a string literal unless `system` is known to quote identifiers with it,
+/// and dollar-quoted strings such as `$$..$$` and `$tag$..$tag$` are masked.
+pub fn sanitize(sql: &str, system: Option<&str>) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut prev = ' ';
+    while let Some(c) = chars.next() {
+        match c {
+            '$' if !(prev.is_alphanumeric() || prev == '_') => {
+                // `$` followed by an optional tag and `$`, otherwise a placeholder such as `$1`
+                let rest: String = chars.clone().collect();
+                let tag_len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                let is_tag = rest[tag_len..].starts_with('$')
+                    && !rest.starts_with(|c: char| c.is_ascii_digit());
+                if !is_tag {
+                    out.push(c);
+                } else {
+                    let delimiter = format!("${}", &rest[..=tag_len]);
+                    let body = &rest[tag_len + 1..];
+                    let end = body
+                        .find(&delimiter)
+                        .map(|i| tag_len + 1 + i + delimiter.len())
+                        .unwrap_or(rest.len());
+                    for _ in rest[..end].chars() {
+                        chars.next();
+                    }
+                    out.push('?');
+                }
+            }
+            '"' if !quotes_identifier(system) => {
+                while let Some(c) = chars.next() {
+                    if c == '"' {
+                        if chars.peek() == Some(&'"') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                out.push('?');
+            }
+            '\'' => {
+                while let Some(c) = chars.next() {
+                    if c == '\'' {
+                        if chars.peek() == Some(&'\'') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                out.push('?');
+            }
+            // not the number of a placeholder such as `$1` or `?1`
+            '0'..='9' if !(prev.is_alphanumeric() || matches!(prev, '_' | '$' | '?')) => {
+                while chars
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '.')
+                {
+                    chars.next();
+                }
+                out.push('?');
+            }
+            '"' | '`' => {
+                out.push(c);
+                for d in chars.by_ref() {
+                    out.push(d);
+                    if d == c {
+                        break;
+                    }
+                }
+            }
+            c => out.push(c),
+        }
+        prev = out.chars().next_back().unwrap_or(' ');
+    }
+    out
+}
+
+/// Index of the token just after the first `keyword` outside of parentheses
+fn top_level_keyword(tokens: &[Token], keyword: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Punct('(') => depth += 1,
+            Token::Punct(')') => depth -= 1,
+            _ if depth == 0 && is_keyword(Some(token), keyword) => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Table name at `i`, unless it is a subquery or a list of tables
+fn table(tokens: &[Token], i: usize) -> Option<String> {
+    let Some(Token::Word { text, .. }) = tokens.get(i) else {
+        return None;
+    };
+    // FROM a, b
+    let next = match tokens.get(i + 1) {
+        Some(Token::Word { quoted: false, .. }) if !is_keyword(tokens.get(i + 1), "AS") => {
+            tokens.get(i + 2)
+        }
+        Some(Token::Word { quoted: false, .. }) => tokens.get(i + 3),
+        next => next,
+    };
+    if next == Some(&Token::Punct(',')) {
+        return None;
+    }
+    Some(text.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(sql: &str) -> Option<String> {
+        summary(sql).span_name()
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(name("SELECT 1"), Some("SELECT".to_string()));
+        assert_eq!(
+            name("select * from users where id = ?"),
+            Some("SELECT users".to_string()),
+        );
+        assert_eq!(
+            name("SELECT (SELECT max(id) FROM logs) AS m, name FROM \"public\".\"users\" u"),
+            Some("SELECT public.users".to_string()),
+        );
+        assert_eq!(
+            name("SELECT * FROM users u, groups g WHERE u.gid = g.id"),
+            Some("SELECT".to_string()),
+        );
+        assert_eq!(
+            name("SELECT * FROM (SELECT 1) AS t"),
+            Some("SELECT".to_string())
+        );
+        assert_eq!(
+            name("-- comment\n INSERT INTO `users` (name) VALUES ('a''b')"),
+            Some("INSERT users".to_string()),
+        );
+        assert_eq!(
+            name("UPDATE users SET name = 'from' WHERE id = 1"),
+            Some("UPDATE users".to_string()),
+        );
+        assert_eq!(
+            name("DELETE FROM users WHERE id = 1"),
+            Some("DELETE users".to_string()),
+        );
+        assert_eq!(
+            name("CREATE TABLE IF NOT EXISTS users (id INTEGER)"),
+            Some("CREATE users".to_string()),
+        );
+        assert_eq!(name("/* x */ BEGIN"), Some("BEGIN".to_string()));
+        assert_eq!(name(""), None);
+    }
+
+    #[test]
+    fn test_db_system() {
+        assert_eq!(db_system("MySqlPool"), Some("mysql"));
+        assert_eq!(db_system("MysqlConnection"), Some("mysql"));
+        assert_eq!(db_system("PgConnection"), Some("postgresql"));
+        assert_eq!(db_system("AsyncPgConnection"), Some("postgresql"));
+        assert_eq!(db_system("Postgres"), Some("postgresql"));
+        assert_eq!(db_system("SqlitePool"), Some("sqlite"));
+        assert_eq!(db_system("sqlite"), Some("sqlite"));
+        assert_eq!(db_system("MultiplexedConnection"), Some("redis"));
+        assert_eq!(db_system("deadpool_redis"), Some("redis"));
+        assert_eq!(db_system("tokio_postgres"), Some("postgresql"));
+        assert_eq!(db_system("Page"), None);
+        assert_eq!(db_system("Pool"), None);
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(
+            sanitize(
+                "SELECT * FROM users WHERE name = 'it''s' AND age > 20",
+                None
+            ),
+            "SELECT * FROM users WHERE name = ? AND age > ?",
+        );
+        assert_eq!(
+            sanitize(
+                "SELECT t1.id FROM t1 WHERE id = $1 LIMIT 1.5",
+                Some("postgresql")
+            ),
+            "SELECT t1.id FROM t1 WHERE id = $1 LIMIT ?",
+        );
+        assert_eq!(
+            sanitize(
+                r#"SELECT "col1" FROM `tbl2` WHERE x IN (1, 0x2F)"#,
+                Some("sqlite")
+            ),
+            r#"SELECT "col1" FROM `tbl2` WHERE x IN (?, ?)"#,
+        );
+        assert_eq!(
+            sanitize("SELECT * FROM t WHERE id = ?1 AND n > 2", Some("sqlite")),
+            "SELECT * FROM t WHERE id = ?1 AND n > ?",
+        );
+    }
+
+    #[test]
+    fn test_sanitize_double_quoted() {
+        // a string literal in MySQL, and when the database is unknown
+        let sql = r#"SELECT `id` FROM users WHERE name = "alice" AND nick = "a""b""#;
+        assert_eq!(
+            sanitize(sql, Some("mysql")),
+            "SELECT `id` FROM users WHERE name = ? AND nick = ?",
+        );
+        assert_eq!(
+            sanitize(sql, None),
+            "SELECT `id` FROM users WHERE name = ? AND nick = ?",
+        );
+        assert_eq!(
+            sanitize(r#"SELECT "name" FROM users"#, Some("postgresql")),
+            r#"SELECT "name" FROM users"#,
+        );
+    }
+
+    #[test]
+    fn test_sanitize_dollar_quoted() {
+        assert_eq!(
+            sanitize(
+                "SELECT * FROM users WHERE name = $$it's$$ AND id = $1",
+                Some("postgresql")
+            ),
+            "SELECT * FROM users WHERE name = ? AND id = $1",
+        );
+        assert_eq!(
+            sanitize(
+                "INSERT INTO t (body) VALUES ($tag$a $$ b$tag$)",
+                Some("postgresql")
+            ),
+            "INSERT INTO t (body) VALUES (?)",
+        );
+        // unterminated
+        assert_eq!(sanitize("SELECT $$secret", Some("postgresql")), "SELECT ?");
+        // `$` in an identifier
+        assert_eq!(
+            sanitize("SELECT a$b$ FROM t", Some("postgresql")),
+            "SELECT a$b$ FROM t",
+        );
+    }
+}
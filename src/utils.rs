@@ -1,4 +1,6 @@
-use syn::Path;
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{Path, Type};
 
 pub(crate) struct PathPat {
     segments: Vec<Vec<String>>,
@@ -23,6 +25,29 @@ pub(crate) fn path_match<P: Into<PathPat>>(path: &Path, pat: P) -> bool {
     }
 }
 
+/// Whether `name` appears in the tokens, including nested groups
+pub(crate) fn has_ident(tokens: TokenStream, name: &str) -> bool {
+    tokens.into_iter().any(|tt| match tt {
+        TokenTree::Ident(ident) => ident == name,
+        TokenTree::Group(g) => has_ident(g.stream(), name),
+        _ => false,
+    })
+}
+
+/// Short name of a type, e.g. `UserRepo` for `crate::repo::UserRepo<T>`
+pub(crate) fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Path(path) => match path.path.segments.last() {
+            Some(seg) => seg.ident.to_string(),
+            None => String::new(),
+        },
+        Type::Reference(r) => type_name(&r.elem),
+        Type::Paren(p) => type_name(&p.elem),
+        Type::Group(g) => type_name(&g.elem),
+        _ => ty.to_token_stream().to_string().replace(' ', ""),
+    }
+}
+
 impl From<&str> for PathPat {
     fn from(s: &str) -> Self {
         PathPat {
@@ -78,4 +103,13 @@ mod tests {
         assert!(path_match(&p("a"), vec![vec!["a", "b"]]));
         assert!(!path_match(&p("a"), vec![vec!["b", "c"]]));
     }
+
+    #[test]
+    fn test_type_name() {
+        let t = |s| type_name(&syn::parse_str(s).unwrap());
+        assert_eq!(t("UserRepo"), "UserRepo");
+        assert_eq!(t("crate::repo::Repo<T>"), "Repo");
+        assert_eq!(t("&'a mut Repo"), "Repo");
+        assert_eq!(t("(i32, i32)"), "(i32,i32)");
+    }
 }
@@ -0,0 +1,61 @@
+// `cargo test --test test_disabled`, with `--features disabled` or `AUTO_SPAN_DISABLE=1` for the turned off path
+
+use std::sync::{Arc, Mutex};
+
+use opentelemetry::global;
+use opentelemetry_auto_span::auto_span;
+use otel_test_helper::{TestTracerProvider, TestTracerProviderInner};
+
+/// The `disabled` feature, or `AUTO_SPAN_DISABLE` as read by the macro
+fn disabled() -> bool {
+    cfg!(feature = "disabled") || matches!(option_env!("AUTO_SPAN_DISABLE"), Some("1" | "true"))
+}
+
+#[auto_span]
+fn f(x: i32) -> Result<i32, &'static str> {
+    if x < 0 {
+        Err("x is negative")
+    } else {
+        Ok(x * x)
+    }
+}
+
+struct UserRepo;
+
+#[auto_span]
+impl UserRepo {
+    async fn find(&self, id: i32) -> Option<i32> {
+        Some(id)
+    }
+}
+
+#[auto_span(enabled = false)]
+fn not_enabled(x: i32) -> i32 {
+    x + 1
+}
+
+#[tokio::test]
+async fn pass_through() {
+    let inner = Arc::new(Mutex::new(TestTracerProviderInner::new()));
+    let _ = global::set_tracer_provider(TestTracerProvider::new(inner.clone()));
+
+    assert_eq!(not_enabled(1), 2);
+    assert!(inner.lock().unwrap().spans.is_empty());
+
+    assert_eq!(f(2), Ok(4));
+    assert!(f(-1).is_err());
+    assert_eq!(UserRepo.find(1).await, Some(1));
+
+    let names: Vec<_> = inner
+        .lock()
+        .unwrap()
+        .spans
+        .iter()
+        .map(|(_, data)| data.name.to_string())
+        .collect();
+    if disabled() {
+        assert!(names.is_empty());
+    } else {
+        assert_eq!(names, ["fn:f", "fn:f", "fn:UserRepo::find"]);
+    }
+}
@@ -1,6 +1,14 @@
+// the spans are not started with the `disabled` feature
+#![cfg(not(feature = "disabled"))]
+
 use std::sync::{Arc, Mutex};
 
-use opentelemetry::{global, trace::Status, Key, Value};
+use opentelemetry::{
+    global,
+    propagation::{text_map_propagator::FieldIter, Extractor, Injector, TextMapPropagator},
+    trace::{SpanKind, Status, TraceContextExt},
+    Context, Key, StringValue, Value,
+};
 use opentelemetry_auto_span::auto_span;
 use otel_test_helper::{TestTracerProvider, TestTracerProviderInner};
 
@@ -32,27 +40,613 @@ async fn test_sqlx() -> sqlx::Result<()> {
     use sqlx::Connection;
     let mut con = sqlx::sqlite::SqliteConnection::connect(":memory:").await?;
     let _ = sqlx::query("SELECT 1").fetch_one(&mut con).await?;
+    sqlx::query("CREATE TABLE users (id INTEGER)")
+        .execute(&mut con)
+        .await?;
+    count_users(&mut con).await?;
+    sanitized_sql(&mut con).await?;
+    dynamic_sql(&mut con).await?;
+    db_params_rows(&mut con).await?;
+    transaction(&mut con).await?;
+    query_macro(&mut con).await?;
+    Ok(())
+}
+
+// the macro describes the query at compile time, with `DATABASE_URL` in `.cargo/config.toml`
+#[auto_span]
+async fn query_macro(con: &mut sqlx::SqliteConnection) -> sqlx::Result<()> {
+    let _ = sqlx::query_unchecked!("SELECT 3 AS three")
+        .fetch_one(&mut *con)
+        .await?;
+    Ok(())
+}
+
+#[auto_span]
+async fn transaction(con: &mut sqlx::SqliteConnection) -> sqlx::Result<()> {
+    use sqlx::Connection;
+    let tx = con.begin().await?;
+    tx.rollback().await?;
+    let tx = con.begin().await?;
+    tx.commit().await?;
+    let pool = sqlx::SqlitePool::connect(":memory:").await?;
+    let _conn = pool.acquire().await?;
+    Ok(())
+}
+
+#[auto_span(db_params, db_rows)]
+async fn db_params_rows(con: &mut sqlx::SqliteConnection) -> sqlx::Result<()> {
+    let name = "x".repeat(200);
+    sqlx::query("INSERT INTO users (id) VALUES (?), (?)")
+        .bind(7)
+        .bind(&name)
+        .execute(&mut *con)
+        .await?;
+    let _ = sqlx::query("SELECT id FROM users")
+        .fetch_all(&mut *con)
+        .await?;
+    Ok(())
+}
+
+#[auto_span]
+async fn dynamic_sql(con: &mut sqlx::SqliteConnection) -> sqlx::Result<()> {
+    let _ = sqlx::query(&format!("SELECT {} FROM users", "id"))
+        .fetch_all(&mut *con)
+        .await?;
+    let sql = String::from("SELECT 2");
+    let _ = sqlx::query(sql.as_str()).fetch_one(&mut *con).await?;
+    let mut builder = sqlx::QueryBuilder::<sqlx::Sqlite>::new("SELECT id FROM users WHERE id = ");
+    builder.push_bind(1);
+    let _ = builder.build().fetch_optional(&mut *con).await?;
+    Ok(())
+}
+
+#[auto_span(sql = "sanitize")]
+async fn sanitized_sql(con: &mut sqlx::SqliteConnection) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO users (id) VALUES (42)")
+        .execute(&mut *con)
+        .await?;
+    no_sql(con).await
+}
+
+#[auto_span(sql = "off")]
+async fn no_sql(con: &mut sqlx::SqliteConnection) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM users WHERE id = 42")
+        .execute(con)
+        .await?;
+    Ok(())
+}
+
+#[auto_span]
+async fn count_users(db: &mut sqlx::SqliteConnection) -> sqlx::Result<i64> {
+    sqlx::query_scalar("SELECT count(*) FROM users")
+        .fetch_one(db)
+        .await
+}
+
+#[auto_span(name = "custom", tracer = "my-component")]
+fn named() {}
+
+#[auto_span(name = "{module}::{fn}")]
+fn named_template() {}
+
+#[auto_span(
+    name = "GET /user/{id}",
+    kind = "server",
+    attributes(http::route = "/user/{id}", retry = 0)
+)]
+async fn named_route() {}
+
+#[derive(Debug)]
+struct UserId(i64);
+
+struct NoDebug;
+
+#[auto_span(args)]
+fn with_args(x: i32, name: &str, id: UserId, db: NoDebug) {
+    let _ = (x, name, id, db);
+}
+
+#[auto_span(skip(db), fields(user_id = id.0, user::kind = "admin"))]
+async fn with_skip(id: UserId, db: NoDebug) {
+    let _ = (id, db);
+}
+
+// the field is recorded by reference, the body can still use the argument
+#[auto_span(fields(user = name))]
+fn with_field(name: String) -> usize {
+    name.len()
+}
+
+#[auto_span(ret)]
+fn ret_value(x: i32) -> i32 {
+    if x < 0 {
+        return -x;
+    }
+    x * 2
+}
+
+#[auto_span(ret)]
+fn ret_result(x: i32) -> Result<i32, &'static str> {
+    Ok(f(x)? + 1)
+}
+
+#[auto_span(ret)]
+async fn ret_option(x: Option<i32>) -> Option<i32> {
+    Some(x? + 1)
+}
+
+struct UserRepo {
+    users: Vec<i32>,
+}
+
+#[auto_span]
+impl UserRepo {
+    async fn find(&self, id: i32) -> Option<i32> {
+        self.users.iter().copied().find(|&u| u == id)
+    }
+
+    fn count(&self) -> Result<usize, &'static str> {
+        Ok(f(self.users.len() as i32)? as usize)
+    }
+
+    #[auto_span(name = "custom_method")]
+    fn custom(&self) {}
+
+    // not instrumented
+    const fn limit() -> usize {
+        10
+    }
+}
+
+#[async_trait::async_trait]
+#[auto_span]
+trait Service {
+    async fn get(&self, x: i32) -> Result<i32, &'static str>;
+
+    async fn get_plus_one(&self, x: i32) -> Result<i32, &'static str> {
+        Ok(self.get(x).await? + 1)
+    }
+}
+
+struct Svc;
+
+#[async_trait::async_trait]
+#[auto_span(ret)]
+impl Service for Svc {
+    async fn get(&self, x: i32) -> Result<i32, &'static str> {
+        f(x)
+    }
+}
+
+#[auto_span]
+trait Greeter {
+    fn hello(&self) -> String {
+        "hello".to_owned()
+    }
+}
+
+impl Greeter for Svc {}
+
+#[auto_span]
+fn early_return(x: i32) -> Result<i32, String> {
+    if x < 0 {
+        return Err(format!("{} is negative", x));
+    }
+    Ok(x)
+}
+
+#[auto_span]
+async fn async_early_return(x: i32) -> Result<i32, String> {
+    if x == 0 {
+        return Err("zero".to_owned());
+    }
+    early_return(x)
+}
+
+#[derive(Debug)]
+struct Cause;
+
+impl std::fmt::Display for Cause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cause")
+    }
+}
+
+impl std::error::Error for Cause {}
+
+#[derive(Debug)]
+struct AppError(Cause);
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "app error")
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[auto_span(exception_events)]
+fn exception_try() -> Result<(), AppError> {
+    Err(AppError(Cause))?;
+    Ok(())
+}
+
+#[auto_span(exception_events)]
+fn exception_tail() -> Result<(), AppError> {
+    Err(AppError(Cause))
+}
+
+#[auto_span]
+fn option_none(x: Option<i32>) -> Option<i32> {
+    let y = x?;
+    Some(y + 1)
+}
+
+#[auto_span]
+fn closure_result(x: i32) -> i32 {
+    let c = |x: i32| -> Result<i32, &'static str> { Ok(f(x)? + 1) };
+    let d = |x: Option<i32>| x.map(|x| x + 1);
+    c(x).unwrap_or(0) + d(None).unwrap_or(0)
+}
+
+#[auto_span]
+fn returns_future(x: i32) -> impl std::future::Future<Output = Result<i32, &'static str>> {
+    async move { Ok(f(x)? + 1) }
+}
+
+type Fallible<T> = Result<T, &'static str>;
+
+#[auto_span]
+fn fallible(x: i32) -> Fallible<i32> {
+    f(x)
+}
+
+#[auto_span(no_result)]
+fn not_result(x: i32) -> Result<i32, &'static str> {
+    f(x)
+}
+
+#[auto_span]
+fn diesel_query() -> diesel::QueryResult<usize> {
+    use diesel::{Connection, RunQueryDsl};
+    let mut conn = diesel::SqliteConnection::establish(":memory:").unwrap();
+    diesel::sql_query("CREATE TABLE users (id INTEGER)").execute(&mut conn)?;
+    diesel_insert(&mut conn)
+}
+
+// the connection is known from the type of the argument
+#[auto_span]
+fn diesel_insert(conn: &mut diesel::SqliteConnection) -> diesel::QueryResult<usize> {
+    use diesel::RunQueryDsl;
+    diesel::sql_query("INSERT INTO users (id) VALUES (1)").execute(conn)
+}
+
+mod user {
+    use sea_orm::entity::prelude::*;
+
+    #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+    #[sea_orm(table_name = "users")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+#[auto_span]
+async fn sea_orm_query() -> Result<usize, sea_orm::DbErr> {
+    use sea_orm::{ConnectionTrait, EntityTrait, Statement};
+    let db = sea_orm::Database::connect("sqlite::memory:").await?;
+    db.execute(Statement::from_string(
+        db.get_database_backend(),
+        "CREATE TABLE users (id INTEGER PRIMARY KEY)",
+    ))
+    .await?;
+    let users = user::Entity::find().all(&db).await?;
+    Ok(users.len())
+}
+
+// only to check that the generated code compiles, there is no server to connect to
+#[allow(dead_code)]
+#[auto_span]
+async fn tokio_postgres_query(
+    client: &tokio_postgres::Client,
+    id: i32,
+) -> Result<u64, tokio_postgres::Error> {
+    let _ = client
+        .query("SELECT * FROM users WHERE id = $1", &[&id])
+        .await?;
+    client
+        .execute("DELETE FROM users WHERE id = $1", &[&id])
+        .await
+}
+
+// only to check that the generated code compiles, there is no server to connect to
+#[allow(dead_code)]
+#[auto_span(db_params)]
+async fn redis_query(client: &redis::Client) -> redis::RedisResult<Option<String>> {
+    use redis::AsyncCommands;
+    let mut con = client.get_multiplexed_async_connection().await?;
+    redis::cmd("SET")
+        .arg("key")
+        .arg(1)
+        .query_async::<()>(&mut con)
+        .await?;
+    con.set::<_, _, ()>("key", 2).await?;
+    con.get("key").await
+}
+
+#[auto_span]
+async fn spawn_tasks() -> Result<i32, &'static str> {
+    let a = tokio::spawn(async { f(2) }).await.unwrap()?;
+    let b = tokio::task::spawn_blocking(|| f(3)).await.unwrap()?;
+    let c = std::thread::spawn(|| f(4)).join().unwrap()?;
+    Ok(a + b + c)
+}
+
+#[auto_span(spawn_span)]
+async fn spawn_with_span() -> Result<i32, &'static str> {
+    tokio::spawn(async move { f(5) }).await.unwrap()
+}
+
+// the function span ends at return, before the detached thread finishes
+#[auto_span]
+fn detached() -> std::thread::JoinHandle<Result<i32, &'static str>> {
+    std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        f(6)
+    })
+}
+
+/// An error which panics if it is formatted
+#[derive(Debug)]
+struct Unformatted;
+
+impl std::fmt::Display for Unformatted {
+    fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        panic!("formatted for a span which is not recording")
+    }
+}
+
+impl std::error::Error for Unformatted {}
+
+#[auto_span(exception_events, ret)]
+fn unformatted() -> Result<(), Unformatted> {
+    Err(Unformatted)
+}
+
+#[auto_span(exception_events, ret)]
+fn not_recording() -> Result<(), Unformatted> {
+    unformatted()?;
     Ok(())
 }
 
+#[auto_span(enabled = false)]
+fn disabled() -> Result<i32, &'static str> {
+    f(-1)
+}
+
+#[auto_span(enabled = cfg!(not(test)))]
+fn disabled_by_cfg() -> Result<i32, &'static str> {
+    f(-2)
+}
+
+#[auto_span(enabled = cfg!(test))]
+fn enabled_by_cfg() {}
+
+#[auto_span(exception_events)]
+fn unit_error(x: i32) -> Result<i32, ()> {
+    if x < 0 {
+        return Err(());
+    }
+    Ok(x)
+}
+
+#[derive(Debug)]
+struct DebugOnly;
+
+// the error type is known even though the `Ok` type is `impl Trait`
+#[auto_span(exception_events)]
+fn impl_ok(x: i32) -> Result<impl std::fmt::Display, DebugOnly> {
+    if x < 0 {
+        return Err(DebugOnly);
+    }
+    Ok(x)
+}
+
+/// Not a `Result` though the name ends with `Result`
+struct CountResult(u64);
+
+#[auto_span]
+fn count_result() -> CountResult {
+    CountResult(1)
+}
+
+macro_rules! bail {
+    ($e:expr) => {
+        return Err($e)
+    };
+}
+
+#[auto_span]
+fn bails(x: i32) -> Result<i32, &'static str> {
+    if x < 0 {
+        bail!("bailed");
+    }
+    Ok(x)
+}
+
+#[auto_span(ret)]
+fn first_mut(v: &mut [i32]) -> Option<&mut i32> {
+    v.first_mut()
+}
+
+#[auto_span(exception_events)]
+fn converted(s: &str) -> Result<i32, Box<dyn std::error::Error>> {
+    let x: i32 = s.parse()?;
+    Ok(x)
+}
+
+#[auto_span(exception_events)]
+fn boxed_error() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    Err(Box::new(AppError(Cause)))
+}
+
+/// Called before and after the tracer provider is set
+#[auto_span]
+fn early() {}
+
+/// Answer one request with `404 Not Found`, and return the request
+fn http_server() -> (String, std::thread::JoinHandle<String>) {
+    use std::io::{Read, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/users", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        let n = stream.read(&mut buf).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+            .unwrap();
+        String::from_utf8_lossy(&buf[..n]).to_string()
+    });
+    (url, handle)
+}
+
+/// Inject the span context as `traceparent`
+#[derive(Debug)]
+struct TraceParent(Vec<String>);
+
+impl TextMapPropagator for TraceParent {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let span_context = cx.span().span_context().clone();
+        injector.set(
+            "traceparent",
+            format!(
+                "00-{}-{}-01",
+                span_context.trace_id(),
+                span_context.span_id()
+            ),
+        );
+    }
+
+    fn extract_with_context(&self, cx: &Context, _extractor: &dyn Extractor) -> Context {
+        cx.clone()
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        FieldIter::new(&self.0)
+    }
+}
+
+#[auto_span(propagate)]
+async fn http_get(url: &str) -> reqwest::Result<u16> {
+    let client = reqwest::Client::new();
+    let res = client.get(url).send().await?;
+    // nobody listens on the port 1
+    let refused = reqwest::get("http://127.0.0.1:1/").await;
+    assert!(refused.is_err());
+    Ok(res.status().as_u16())
+}
+
 #[tokio::test]
 async fn main() {
+    // no tracer provider yet, the tracer must not be cached
+    early();
+
     // setup
     let inner = Arc::new(Mutex::new(TestTracerProviderInner::new()));
     let provider = TestTracerProvider::new(inner.clone());
     let _ = global::set_tracer_provider(provider);
+    global::set_text_map_propagator(TraceParent(vec!["traceparent".to_string()]));
 
     // call test target functions
     let _ = g(12);
     let _ = h(Some(1));
     assert!(test_sqlx().await.is_ok());
+    named();
+    named_template();
+    named_route().await;
+    with_args(3, "abc", UserId(1), NoDebug);
+    with_skip(UserId(42), NoDebug).await;
+    assert_eq!(with_field("alice".to_owned()), 5);
+    assert_eq!(ret_value(-3), 3);
+    assert!(ret_result(-1).is_err());
+    assert_eq!(ret_option(None).await, None);
+    let repo = UserRepo {
+        users: vec![1, 2, 3],
+    };
+    assert_eq!(repo.find(2).await, Some(2));
+    assert_eq!(repo.count(), Ok(9));
+    repo.custom();
+    assert_eq!(UserRepo::limit(), 10);
+    assert_eq!(Svc.get_plus_one(-2).await, Err("x is negative"));
+    assert_eq!(Svc.hello(), "hello");
+    assert!(early_return(-1).is_err());
+    assert!(early_return(1).is_ok());
+    assert!(async_early_return(0).await.is_err());
+    assert!(async_early_return(-5).await.is_err());
+    assert!(exception_try().is_err());
+    assert!(exception_tail().is_err());
+    assert_eq!(option_none(None), None);
+    assert_eq!(option_none(Some(1)), Some(2));
+    assert_eq!(closure_result(-1), 0);
+    let future = returns_future(-1);
+    assert!(future.await.is_err());
+    assert!(fallible(-1).is_err());
+    assert!(not_result(-1).is_err());
+    assert_eq!(diesel_query(), Ok(1));
+    assert_eq!(sea_orm_query().await, Ok(0));
+    let (url, server) = http_server();
+    assert_eq!(http_get(&url).await.unwrap(), 404);
+    let request = server.join().unwrap();
+    assert_eq!(spawn_tasks().await, Ok(29));
+    assert_eq!(spawn_with_span().await, Ok(25));
+    assert_eq!(detached().join().unwrap(), Ok(36));
+    inner.lock().unwrap().sampled = false;
+    assert!(not_recording().is_err());
+    inner.lock().unwrap().sampled = true;
+    assert!(disabled().is_err());
+    assert!(disabled_by_cfg().is_err());
+    enabled_by_cfg();
+    assert_eq!(unit_error(-1), Err(()));
+    assert!(impl_ok(-1).is_err());
+    assert_eq!(count_result().0, 1);
+    assert_eq!(bails(-1), Err("bailed"));
+    let mut v = vec![1];
+    *first_mut(&mut v).unwrap() += 1;
+    assert_eq!(v, vec![2]);
+    assert!(converted("x").is_err());
+    assert!(boxed_error().is_err());
+    early();
 
     // check result
     let spans = &inner.lock().unwrap().spans;
     let mut span_iter = spans.iter();
-    // g から f を2回呼ぶ。g だけがエラーになる仕様
-    assert_eq!(span_iter.next().unwrap().1.name, "fn:f");
-    assert_eq!(span_iter.next().unwrap().1.name, "fn:f");
+    // g から f を2回呼ぶ。f は返り値の Err で、g は ? でエラーになる
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:f");
+        assert_eq!(data.library.name, "opentelemetry-auto-span");
+        assert_eq!(
+            data.library.version.as_deref(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(data.status, Status::Unset);
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:f");
+        assert_eq!(data.status, Status::error("x is negative"));
+    }
     {
         let data = &span_iter.next().unwrap().1;
         assert_eq!(data.name, "fn:g");
@@ -72,14 +666,511 @@ async fn main() {
     // test_sqlx
     {
         let data = &span_iter.next().unwrap().1;
-        assert_eq!(data.name, "db"); // TODO: line 対応したら直す
+        assert_eq!(data.name, "SELECT");
         assert_eq!(
             data.attributes.get(&Key::new("db.statement")).unwrap(),
             &Value::from("SELECT 1"),
         );
+        assert_eq!(
+            data.attributes.get(&Key::new("db.system")).unwrap(),
+            &Value::from("sqlite"),
+        );
+        assert_eq!(
+            data.attributes.get(&Key::new("db.operation.name")).unwrap(),
+            &Value::from("SELECT"),
+        );
+        assert_eq!(data.attributes.get(&Key::new("db.collection.name")), None);
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "CREATE users");
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "SELECT users");
+        assert_eq!(
+            data.attributes.get(&Key::new("db.system")).unwrap(),
+            &Value::from("sqlite"),
+        );
+        assert_eq!(
+            data.attributes
+                .get(&Key::new("db.collection.name"))
+                .unwrap(),
+            &Value::from("users"),
+        );
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:count_users");
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "INSERT users");
+        assert_eq!(
+            data.attributes.get(&Key::new("db.statement")).unwrap(),
+            &Value::from("INSERT INTO users (id) VALUES (?)"),
+        );
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "DELETE users");
+        assert_eq!(data.attributes.get(&Key::new("db.statement")), None);
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:no_sql");
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:sanitized_sql");
+    for statement in [
+        "SELECT id FROM users",
+        "SELECT 2",
+        "SELECT id FROM users WHERE id = ?",
+    ] {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "db");
+        assert_eq!(
+            data.attributes.get(&Key::new("db.statement")).unwrap(),
+            &Value::from(statement),
+        );
+        assert_eq!(
+            data.attributes.get(&Key::new("db.system")).unwrap(),
+            &Value::from("sqlite"),
+        );
     }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:dynamic_sql");
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "INSERT users");
+        assert_eq!(
+            data.attributes
+                .get(&Key::new("db.query.parameter.0"))
+                .unwrap(),
+            &Value::from("7"),
+        );
+        assert_eq!(
+            data.attributes
+                .get(&Key::new("db.query.parameter.1"))
+                .unwrap(),
+            &Value::from(format!("\"{}...", "x".repeat(127))),
+        );
+        assert_eq!(
+            data.attributes
+                .get(&Key::new("db.response.affected_rows"))
+                .unwrap(),
+            &Value::I64(2),
+        );
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "SELECT users");
+        assert_eq!(
+            data.attributes
+                .get(&Key::new("db.response.returned_rows"))
+                .unwrap(),
+            &Value::I64(2),
+        );
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:db_params_rows");
+    for name in [
+        "db.transaction.begin",
+        "db.transaction.rollback",
+        "db.transaction.begin",
+        "db.transaction.commit",
+        "db.acquire",
+    ] {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, name);
+        assert_eq!(
+            data.attributes.get(&Key::new("db.system")).unwrap(),
+            &Value::from("sqlite"),
+        );
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:transaction");
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "SELECT");
+        assert_eq!(
+            data.attributes.get(&Key::new("db.statement")).unwrap(),
+            &Value::from("SELECT 3 AS three"),
+        );
+        assert_eq!(
+            data.attributes.get(&Key::new("db.system")).unwrap(),
+            &Value::from("sqlite"),
+        );
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:query_macro");
     {
         let data = &span_iter.next().unwrap().1;
         assert_eq!(data.name, "fn:test_sqlx");
     }
+    // custom names
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "custom");
+        assert_eq!(data.library.name, "my-component");
+        assert_eq!(
+            data.library.version.as_deref(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+    }
+    assert_eq!(
+        span_iter.next().unwrap().1.name,
+        "test_span::named_template"
+    );
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "GET /user/{id}");
+        assert_eq!(data.kind, SpanKind::Server);
+        assert_eq!(
+            data.attributes.get(&Key::new("http.route")).unwrap(),
+            &Value::from("/user/{id}"),
+        );
+        assert_eq!(
+            data.attributes.get(&Key::new("retry")).unwrap(),
+            &Value::I64(0),
+        );
+    }
+    // arguments
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:with_args");
+        assert_eq!(data.attributes.len(), 4);
+        assert_eq!(
+            data.attributes.get(&Key::new("code.arg.x")).unwrap(),
+            &Value::from("3"),
+        );
+        assert_eq!(
+            data.attributes.get(&Key::new("code.arg.name")).unwrap(),
+            &Value::from("abc"),
+        );
+        assert_eq!(
+            data.attributes.get(&Key::new("code.arg.id")).unwrap(),
+            &Value::from("UserId(1)"),
+        );
+        assert_eq!(
+            data.attributes.get(&Key::new("code.arg.db")).unwrap(),
+            &Value::from("test_span::NoDebug"),
+        );
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:with_skip");
+        assert_eq!(data.attributes.len(), 3);
+        assert_eq!(
+            data.attributes.get(&Key::new("code.arg.id")).unwrap(),
+            &Value::from("UserId(42)"),
+        );
+        assert_eq!(
+            data.attributes.get(&Key::new("user_id")).unwrap(),
+            &Value::from(42),
+        );
+        assert_eq!(
+            data.attributes.get(&Key::new("user.kind")).unwrap(),
+            &Value::from("admin"),
+        );
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:with_field");
+        assert_eq!(
+            data.attributes.get(&Key::new("user")).unwrap(),
+            &Value::from("alice"),
+        );
+    }
+    // return value
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:ret_value");
+        assert_eq!(
+            data.attributes.get(&Key::new("code.return")).unwrap(),
+            &Value::from("3"),
+        );
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:f");
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:ret_result");
+        assert_eq!(
+            data.attributes.get(&Key::new("code.return")).unwrap(),
+            &Value::from("Err"),
+        );
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:ret_option");
+        assert_eq!(
+            data.attributes.get(&Key::new("code.return")).unwrap(),
+            &Value::from("None"),
+        );
+    }
+    // impl block
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:UserRepo::find");
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:f");
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:UserRepo::count");
+    assert_eq!(span_iter.next().unwrap().1.name, "custom_method");
+    // trait
+    let (f_cx, _) = span_iter.next().unwrap();
+    {
+        let (cx, data) = span_iter.next().unwrap();
+        assert_eq!(data.name, "fn:Svc::get");
+        assert_eq!(cx.trace_id(), f_cx.trace_id());
+        assert_eq!(
+            data.attributes.get(&Key::new("code.return")).unwrap(),
+            &Value::from("Err"),
+        );
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:Service::get_plus_one");
+        assert_eq!(data.status, Status::error("x is negative"));
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:Greeter::hello");
+    // error status from the returned value
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:early_return");
+        assert_eq!(data.status, Status::error("-1 is negative"));
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:early_return");
+        assert_eq!(data.status, Status::Unset);
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:async_early_return");
+        assert_eq!(data.status, Status::error("zero"));
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:early_return");
+        assert_eq!(data.status, Status::error("-5 is negative"));
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:async_early_return");
+        assert_eq!(data.status, Status::error("-5 is negative"));
+    }
+    // exception events
+    for name in ["fn:exception_try", "fn:exception_tail"] {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, name);
+        assert_eq!(data.status, Status::error("app error"));
+        assert_eq!(data.events.len(), 1);
+        let event = &data.events[0];
+        assert_eq!(event.name, "exception");
+        let attr = |key: &str| {
+            event
+                .attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == key)
+                .unwrap()
+                .value
+                .clone()
+        };
+        assert_eq!(attr("exception.type"), Value::from("test_span::AppError"));
+        assert_eq!(attr("exception.message"), Value::from("app error"));
+        assert_eq!(
+            attr("auto_span.exception.causes"),
+            Value::Array(vec![StringValue::from("cause")].into()),
+        );
+    }
+    // ? in Option and closure
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:option_none");
+        assert_eq!(data.events.len(), 1);
+        assert_eq!(data.events[0].name, "option.none");
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:option_none");
+        assert!(data.events.is_empty());
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:f");
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:closure_result");
+        // the error is handled by `unwrap_or`, so only an event is recorded
+        assert_eq!(data.status, Status::Unset);
+        assert_eq!(data.events.len(), 1);
+        assert_eq!(data.events[0].name, "exception");
+        assert!(data.events[0]
+            .attributes
+            .iter()
+            .any(|kv| kv.key.as_str() == "code.lineno"));
+    }
+    // return type analysis
+    let (f_cx, _) = span_iter.next().unwrap();
+    {
+        let (cx, data) = span_iter.next().unwrap();
+        assert_eq!(data.name, "fn:returns_future");
+        assert_eq!(cx.trace_id(), f_cx.trace_id());
+        assert_eq!(data.status, Status::error("x is negative"));
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:f");
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:fallible");
+        assert_eq!(data.status, Status::error("x is negative"));
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:f");
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:not_result");
+        assert_eq!(data.status, Status::Unset);
+    }
+    // diesel
+    for (name, statement) in [
+        ("CREATE users", "CREATE TABLE users (id INTEGER)"),
+        ("INSERT users", "INSERT INTO users (id) VALUES (1)"),
+    ] {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, name);
+        assert_eq!(
+            data.attributes.get(&Key::new("db.statement")).unwrap(),
+            &Value::from(statement),
+        );
+        assert_eq!(
+            data.attributes.get(&Key::new("db.system")).unwrap(),
+            &Value::from("sqlite"),
+        );
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:diesel_insert");
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:diesel_query");
+    // sea-orm
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "CREATE users");
+        assert_eq!(
+            data.attributes.get(&Key::new("db.statement")).unwrap(),
+            &Value::from("CREATE TABLE users (id INTEGER PRIMARY KEY)"),
+        );
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "db");
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:sea_orm_query");
+    // reqwest
+    {
+        let (cx, data) = span_iter.next().unwrap();
+        assert_eq!(data.name, "HTTP GET");
+        assert_eq!(data.kind, SpanKind::Client);
+        assert_eq!(
+            data.attributes
+                .get(&Key::new("http.request.method"))
+                .unwrap(),
+            &Value::from("GET"),
+        );
+        assert_eq!(
+            data.attributes
+                .get(&Key::new("http.response.status_code"))
+                .unwrap(),
+            &Value::I64(404),
+        );
+        assert_eq!(data.attributes.get(&Key::new("url.full")), None);
+        assert_eq!(data.status, Status::error(""));
+        assert!(request.contains(&format!(
+            "traceparent: 00-{}-{}-01",
+            cx.trace_id(),
+            cx.span_id()
+        )));
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "HTTP GET");
+        assert_eq!(
+            data.attributes.get(&Key::new("url.full")).unwrap(),
+            &Value::from("http://127.0.0.1:1/"),
+        );
+        assert!(matches!(data.status, Status::Error { .. }));
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:http_get");
+    // spawn
+    {
+        let spawned: Vec<_> = (0..3).map(|_| span_iter.next().unwrap()).collect();
+        let (cx, data) = span_iter.next().unwrap();
+        assert_eq!(data.name, "fn:spawn_tasks");
+        for (f_cx, f_data) in spawned {
+            assert_eq!(f_data.name, "fn:f");
+            assert_eq!(f_cx.trace_id(), cx.trace_id());
+        }
+    }
+    {
+        let (f_cx, f_data) = span_iter.next().unwrap();
+        assert_eq!(f_data.name, "fn:f");
+        let (spawn_cx, spawn_data) = span_iter.next().unwrap();
+        assert_eq!(spawn_data.name, "spawn");
+        let (cx, data) = span_iter.next().unwrap();
+        assert_eq!(data.name, "fn:spawn_with_span");
+        assert_eq!(f_cx.trace_id(), cx.trace_id());
+        assert_eq!(spawn_cx.trace_id(), cx.trace_id());
+    }
+    {
+        let (cx, data) = span_iter.next().unwrap();
+        assert_eq!(data.name, "fn:detached");
+        let (f_cx, f_data) = span_iter.next().unwrap();
+        assert_eq!(f_data.name, "fn:f");
+        assert_eq!(f_cx.trace_id(), cx.trace_id());
+    }
+    // enabled
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:f");
+        assert_eq!(data.status, Status::error("x is negative"));
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:f");
+        assert_eq!(data.status, Status::error("x is negative"));
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:enabled_by_cfg");
+    // returned values
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:unit_error");
+        assert_eq!(data.status, Status::error("()"));
+        assert_eq!(data.events[0].name, "exception");
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:impl_ok");
+        assert_eq!(data.status, Status::error("DebugOnly"));
+        assert_eq!(data.events[0].name, "exception");
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:count_result");
+        assert_eq!(data.status, Status::Unset);
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:bails");
+        assert_eq!(data.status, Status::error("bailed"));
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:first_mut");
+        assert_eq!(
+            data.attributes.get(&Key::new("code.return")).unwrap(),
+            &Value::from("Some"),
+        );
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:converted");
+        assert_eq!(data.status, Status::error("invalid digit found in string"));
+        assert_eq!(data.events[0].name, "exception");
+    }
+    {
+        let data = &span_iter.next().unwrap().1;
+        assert_eq!(data.name, "fn:boxed_error");
+        let event = &data.events[0];
+        let source = event
+            .attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == "auto_span.exception.causes")
+            .unwrap();
+        assert_eq!(
+            source.value,
+            Value::Array(vec![StringValue::from("cause")].into()),
+        );
+    }
+    assert_eq!(span_iter.next().unwrap().1.name, "fn:early");
+    assert!(span_iter.next().is_none());
 }